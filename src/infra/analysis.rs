@@ -0,0 +1,122 @@
+use axum::{extract::Query, response::IntoResponse, routing, Json, Router};
+use reqwest::StatusCode;
+
+use crate::{
+    models::{analysis, Card, MAX_PLAYER_COUNT},
+    services::manager::Manager,
+};
+
+pub fn router() -> Router<Manager> {
+    Router::new().route("/bid", routing::get(get_bid_analysis))
+}
+
+async fn get_bid_analysis(
+    Query(params): Query<BidAnalysisParams>,
+) -> Result<Json<BidAnalysisDto>, AnalysisError> {
+    let hand = params
+        .hand
+        .split(',')
+        .map(parse_card)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let upcard = parse_card(&params.upcard)?;
+
+    validate_params(&hand, upcard, params.players, params.seat)?;
+
+    let possible_bids: Vec<_> = (0..=hand.len()).collect();
+
+    // the simulations take a while, keep them off the async workers
+    let expected_tricks = tokio::task::spawn_blocking(move || {
+        analysis::expected_tricks(&hand, upcard, params.players, params.seat)
+    })
+    .await
+    .map_err(|_| AnalysisError::SimulationFailed)?;
+
+    let recommended_bid =
+        analysis::recommend_bid(expected_tricks, &possible_bids).expect("Should have a bid");
+
+    Ok(Json(BidAnalysisDto {
+        expected_tricks,
+        recommended_bid,
+    }))
+}
+
+fn parse_card(card: &str) -> Result<Card, AnalysisError> {
+    let invalid = || AnalysisError::InvalidCard(card.to_string());
+
+    let (rank, suit) = card.split_once(':').ok_or_else(invalid)?;
+
+    let rank = rank.parse().map_err(|_| invalid())?;
+    let suit = suit.parse().map_err(|_| invalid())?;
+
+    Ok(Card::new(rank, suit))
+}
+
+fn validate_params(
+    hand: &[Card],
+    upcard: Card,
+    players: usize,
+    seat: usize,
+) -> Result<(), AnalysisError> {
+    if !(2..=MAX_PLAYER_COUNT).contains(&players) {
+        return Err(AnalysisError::InvalidPlayerCount);
+    }
+
+    if seat >= players {
+        return Err(AnalysisError::InvalidSeat);
+    }
+
+    let duplicated = hand
+        .iter()
+        .enumerate()
+        .any(|(i, c)| hand[i + 1..].contains(c));
+
+    if duplicated || hand.contains(&upcard) || hand.len() * players >= Card::deck().len() {
+        return Err(AnalysisError::InvalidHand);
+    }
+
+    Ok(())
+}
+
+/// Cards are written as `Rank:Suit` and the hand is a comma separated list of them,
+/// e.g. `?hand=Three:Clubs,Four:Golds&upcard=Seven:Cups&players=4&seat=0`
+#[derive(serde::Deserialize)]
+pub struct BidAnalysisParams {
+    pub hand: String,
+    pub upcard: String,
+    pub players: usize,
+    pub seat: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct BidAnalysisDto {
+    pub expected_tricks: f64,
+    pub recommended_bid: usize,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum AnalysisError {
+    #[error("Invalid card: {0}")]
+    InvalidCard(String),
+    #[error("Invalid player count")]
+    InvalidPlayerCount,
+    #[error("Seat should be lower than the player count")]
+    InvalidSeat,
+    #[error("Invalid hand for this table")]
+    InvalidHand,
+    #[error("Couldn't simulate this hand")]
+    SimulationFailed,
+}
+
+impl IntoResponse for AnalysisError {
+    fn into_response(self) -> axum::response::Response {
+        let code = match self {
+            AnalysisError::SimulationFailed => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+
+        let body = Json(serde_json::json!({"error": self.to_string()}));
+
+        (code, body).into_response()
+    }
+}
@@ -25,6 +25,8 @@ pub fn router() -> Router<Manager> {
 
 pub static JWT_KEY: OnceLock<String> = OnceLock::new();
 
+const INVITE_TTL: chrono::Duration = chrono::Duration::days(7);
+
 pub async fn middleware(mut req: Request, next: Next) -> Result<impl IntoResponse, AuthError> {
     let token = get_token_from_req(&mut req)
         .await
@@ -59,7 +61,7 @@ async fn update_profile(
 ) -> Result<Json<TokenResponse>, impl IntoResponse> {
     let claim = match user_claims {
         UserClaims::Anonymous(c) => c,
-        UserClaims::Google(_) => {
+        UserClaims::Google(_) | UserClaims::Bot(_) => {
             let response = (
                 StatusCode::UNPROCESSABLE_ENTITY,
                 "Google claim not supported for now...",
@@ -141,6 +143,29 @@ pub async fn get_claims_from_token(token: &str) -> Result<UserClaims, AuthError>
     }
 }
 
+pub fn create_invite_token(lobby_id: String, invite_id: String) -> String {
+    let claims = InviteClaims {
+        lobby_id,
+        invite_id,
+        exp: (chrono::Utc::now() + INVITE_TTL).timestamp(),
+    };
+
+    jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(get_key().as_bytes()),
+    )
+    .expect("Should encode JWT")
+}
+
+pub fn get_invite_claims(token: &str) -> Result<InviteClaims, AuthError> {
+    let key = DecodingKey::from_secret(get_key().as_bytes());
+
+    let validation = Validation::new(jsonwebtoken::Algorithm::HS256);
+
+    Ok(jsonwebtoken::decode(token, &key, &validation)?.claims)
+}
+
 async fn get_token_from_req(req: &mut Request) -> Option<&str> {
     req.headers()
         .get(header::AUTHORIZATION)
@@ -222,6 +247,7 @@ impl IntoResponse for AuthError {
 pub enum UserClaims {
     Anonymous(AnonymousUserClaims),
     Google(GoogleUserClaims),
+    Bot(BotClaims),
 }
 
 impl UserClaims {
@@ -229,6 +255,7 @@ impl UserClaims {
         match self {
             UserClaims::Anonymous(a) => a.id.clone(),
             UserClaims::Google(g) => g.email.clone(),
+            UserClaims::Bot(b) => b.id.clone(),
         }
     }
 }
@@ -240,6 +267,28 @@ pub struct AnonymousUserClaims {
     name: String,
 }
 
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct InviteClaims {
+    pub lobby_id: String,
+    pub invite_id: String,
+    exp: i64,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct BotClaims {
+    pub id: String,
+    pub name: String,
+}
+
+impl BotClaims {
+    pub fn new(number: usize) -> Self {
+        Self {
+            id: format!("bot-{}", generate_username()),
+            name: format!("Bot {number}"),
+        }
+    }
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Clone, PartialEq, Eq, Debug)]
 pub struct GoogleUserClaims {
     pub email: String,
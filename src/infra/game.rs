@@ -1,17 +1,21 @@
-use std::net::SocketAddr;
+use std::{borrow::Cow, net::SocketAddr, time::Duration};
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
+        ws::{CloseFrame, Message, WebSocket},
         ConnectInfo, State, WebSocketUpgrade,
     },
     response::IntoResponse,
 };
-use futures::{stream::SplitStream, StreamExt};
+use futures::{
+    stream::{SplitSink, SplitStream},
+    SinkExt, StreamExt,
+};
+use tokio::time::{Instant, Interval};
 
 use crate::{
-    infra::ClientMessage,
-    services::manager::{Manager, ManagerError},
+    infra::{ClientMessage, Protocol, ServerMessage, ACK, MIN_PROTOCOL_VERSION, RESUME},
+    services::manager::{ConnectionId, Manager, ManagerError, RequestState, SessionConfig},
 };
 
 use super::{
@@ -35,45 +39,180 @@ pub async fn ws_handler(
 }
 
 async fn handle_connection(socket: WebSocket, manager: Manager) -> Result<(), ManagerError> {
-    let (sender, mut receiver) = socket.split();
+    let (mut sender, mut receiver) = socket.split();
+
+    let (auth, protocol) = match handshake(&mut sender, &mut receiver, &manager).await {
+        Ok(handshake) => handshake,
+        Err(e) => {
+            let close = Message::Close(Some(CloseFrame {
+                code: e.close_code(),
+                reason: Cow::Owned(e.to_string()),
+            }));
+            _ = sender.send(close).await;
+
+            return Err(e);
+        }
+    };
 
-    let auth = get_auth(&mut receiver).await?;
+    let player_id = auth.id();
 
-    manager.store_player_connection(auth.id(), sender).await?;
+    let (connection_id, writer) = manager
+        .store_player_connection(player_id.clone(), sender, protocol.clone())
+        .await?;
 
-    tokio::spawn(async move {
-        while let Some(Ok(message)) = receiver.next().await {
-            let id = auth.id();
-            match process_msg(message, manager.clone(), id.clone()).await {
-                Ok(_) => {}
-                Err(error) => {
-                    tracing::error!("{id} Error: {error}");
-                    manager.send_error(&id, error).await;
-                    break;
+    manager.player_connected(player_id.clone()).await;
+
+    let reader = {
+        let manager = manager.clone();
+        let mut heartbeat = Heartbeat::new(&manager.session_config());
+
+        async move {
+            loop {
+                let id = auth.id();
+
+                tokio::select! {
+                    message = receiver.next() => {
+                        let Some(Ok(message)) = message else {
+                            break;
+                        };
+
+                        heartbeat.seen();
+
+                        match process_msg(message, manager.clone(), id.clone(), connection_id, &protocol).await {
+                            Ok(_) => {}
+                            Err(error) => {
+                                tracing::error!("{id} Error: {error}");
+                                manager.send_error(&id, connection_id, error).await;
+                                break;
+                            }
+                        }
+                    }
+                    beat = heartbeat.tick() => match beat {
+                        Beat::Ping => manager.ping(&id, connection_id).await,
+                        Beat::Expired => {
+                            tracing::warn!("{id} missed heartbeat, closing stale connection");
+                            manager.close_stale(&id, connection_id).await;
+                            break;
+                        }
+                    }
                 }
             }
         }
-    })
-    .await
-    .expect("This task should complete successfully");
+    };
+
+    let mut reader = tokio::spawn(reader);
+
+    tokio::select! {
+        result = &mut reader => result.expect("This task should complete successfully"),
+        _ = writer => {
+            tracing::warn!("{player_id} outbound connection closed");
+            reader.abort();
+        }
+    }
+
+    manager.player_disconnected(player_id, connection_id).await;
 
     Ok(())
 }
 
-async fn get_auth(receiver: &mut SplitStream<WebSocket>) -> Result<UserClaims, ManagerError> {
-    if let Some(Ok(message)) = receiver.next().await {
-        match message {
-            Message::Text(message) => {
-                let message: ClientMessage = serde_json::from_str(&message)?;
-
-                match message {
-                    ClientMessage::Auth { token } => Ok(auth::get_claims_from_token(&token).await?),
-                    ClientMessage::Game(_) => Err(ManagerError::UnexpectedValidMessage(
-                        "Expected auth message",
-                    )),
-                }
-            }
+enum Beat {
+    Ping,
+    Expired,
+}
+
+/// Pings on every tick and gives up on connections that stayed silent for longer than
+/// the pong timeout
+struct Heartbeat {
+    ticks: Interval,
+    timeout: Duration,
+    last_seen: Instant,
+}
+
+impl Heartbeat {
+    fn new(config: &SessionConfig) -> Self {
+        let ticks =
+            tokio::time::interval_at(Instant::now() + config.ping_interval, config.ping_interval);
+
+        Self {
+            ticks,
+            timeout: config.pong_timeout,
+            last_seen: Instant::now(),
+        }
+    }
+
+    fn seen(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    async fn tick(&mut self) -> Beat {
+        self.ticks.tick().await;
+
+        match self.last_seen.elapsed() > self.timeout {
+            true => Beat::Expired,
+            false => Beat::Ping,
+        }
+    }
+}
+
+async fn handshake(
+    sender: &mut SplitSink<WebSocket, Message>,
+    receiver: &mut SplitStream<WebSocket>,
+    manager: &Manager,
+) -> Result<(UserClaims, Protocol), ManagerError> {
+    // a socket that never authenticates gets no heartbeat, so it only gets this long
+    let timeout = manager.session_config().pong_timeout;
+
+    let (protocol_version, capabilities) = match read_handshake_msg(receiver, timeout).await? {
+        ClientMessage::Hello {
+            protocol_version,
+            capabilities,
+        } => (protocol_version, capabilities),
+        // legacy clients go straight to auth
+        ClientMessage::Auth { token } => {
+            let claims = auth::get_claims_from_token(&token).await?;
+
+            return Ok((claims, Protocol::legacy()));
+        }
+        _ => {
+            return Err(ManagerError::UnexpectedValidMessage(
+                "Expected hello or auth message",
+            ))
+        }
+    };
+
+    if protocol_version < MIN_PROTOCOL_VERSION {
+        return Err(ManagerError::UnsupportedProtocol(protocol_version));
+    }
+
+    let protocol = Protocol::negotiate(protocol_version, capabilities);
 
+    let welcome = ServerMessage::Welcome(manager.welcome(&protocol));
+    let welcome = serde_json::to_string(&welcome).expect("Should be valid json");
+
+    sender
+        .send(Message::Text(welcome))
+        .await
+        .map_err(|e| ManagerError::PlayerDisconnected(e.to_string()))?;
+
+    match read_handshake_msg(receiver, timeout).await? {
+        ClientMessage::Auth { token } => Ok((auth::get_claims_from_token(&token).await?, protocol)),
+        _ => Err(ManagerError::UnexpectedValidMessage(
+            "Expected auth message",
+        )),
+    }
+}
+
+async fn read_handshake_msg(
+    receiver: &mut SplitStream<WebSocket>,
+    timeout: Duration,
+) -> Result<ClientMessage, ManagerError> {
+    let message = tokio::time::timeout(timeout, receiver.next())
+        .await
+        .map_err(|_| ManagerError::HandshakeTimeout)?;
+
+    if let Some(Ok(message)) = message {
+        match message {
+            Message::Text(message) => Ok(serde_json::from_str(&message)?),
             _ => Err(ManagerError::InvalidWebsocketMessageType),
         }
     } else {
@@ -87,20 +226,22 @@ async fn process_msg(
     msg: Message,
     manager: Manager,
     player_id: String,
+    connection_id: ConnectionId,
+    protocol: &Protocol,
 ) -> Result<(), ManagerError> {
     match msg {
         Message::Text(msg) => {
             let msg = serde_json::from_str(&msg)?;
             tracing::debug!("Received from {player_id}: {msg:?}");
 
+            check_capabilities(&msg, protocol)?;
+
             match msg {
-                ClientMessage::Game(g) => handle_game_msg(g, manager, player_id).await,
-                ClientMessage::Auth { token: a } => {
-                    tracing::error!("Unexpected auth message {a}");
-                    Err(ManagerError::UnexpectedValidMessage(
-                        "Expected game message",
-                    ))
+                ClientMessage::Request { id, message } => {
+                    handle_request(id, *message, manager, player_id, connection_id, protocol).await;
+                    Ok(())
                 }
+                msg => handle_client_msg(msg, manager, player_id).await,
             }
         }
         Message::Close(c) => {
@@ -112,10 +253,91 @@ async fn process_msg(
 
             Err(ManagerError::PlayerDisconnected(reason))
         }
+        Message::Ping(_) | Message::Pong(_) => Ok(()),
         _ => Err(ManagerError::InvalidWebsocketMessageType),
     }
 }
 
+/// Messages that rely on a capability the connection didn't negotiate are rejected
+fn check_capabilities(msg: &ClientMessage, protocol: &Protocol) -> Result<(), ManagerError> {
+    let required = match msg {
+        ClientMessage::Request { .. } => ACK,
+        ClientMessage::Game(ClientGameMessage::Resume { .. }) => RESUME,
+        _ => return Ok(()),
+    };
+
+    match protocol.supports(required) {
+        true => Ok(()),
+        false => Err(ManagerError::MissingCapability(required)),
+    }
+}
+
+/// Errors inside a request are answered with a `Nack` and keep the connection open
+async fn handle_request(
+    id: String,
+    msg: ClientMessage,
+    manager: Manager,
+    player_id: String,
+    connection_id: ConnectionId,
+    protocol: &Protocol,
+) {
+    let reply = match manager.start_request(&player_id, &id, connection_id).await {
+        RequestState::Done(reply) => reply,
+        RequestState::Pending => return,
+        RequestState::New => {
+            let result = match check_capabilities(&msg, protocol) {
+                Ok(_) => handle_client_msg(msg, manager.clone(), player_id.clone()).await,
+                Err(e) => Err(e),
+            };
+
+            let reply = match result {
+                Ok(_) => ServerMessage::Ack { id: id.clone() },
+                Err(error) => {
+                    tracing::warn!("{player_id} request {id} failed: {error}");
+                    ServerMessage::Nack {
+                        id: id.clone(),
+                        code: error.code().to_string(),
+                    }
+                }
+            };
+
+            let waiting = manager.finish_request(&player_id, &id, reply.clone()).await;
+
+            for waiting in waiting {
+                manager.reply(&player_id, waiting, &reply).await;
+            }
+
+            reply
+        }
+    };
+
+    manager.reply(&player_id, connection_id, &reply).await;
+}
+
+async fn handle_client_msg(
+    msg: ClientMessage,
+    manager: Manager,
+    player_id: String,
+) -> Result<(), ManagerError> {
+    match msg {
+        ClientMessage::Game(g) => handle_game_msg(g, manager, player_id).await,
+        ClientMessage::Chat { text } => Ok(manager.chat(player_id, text).await?),
+        ClientMessage::React { emote } => Ok(manager.react(player_id, emote).await?),
+        ClientMessage::Auth { token: a } => {
+            tracing::error!("Unexpected auth message {a}");
+            Err(ManagerError::UnexpectedValidMessage(
+                "Expected game message",
+            ))
+        }
+        ClientMessage::Hello { .. } => Err(ManagerError::UnexpectedValidMessage(
+            "Handshake already done",
+        )),
+        ClientMessage::Request { .. } => Err(ManagerError::UnexpectedValidMessage(
+            "Requests can't be nested",
+        )),
+    }
+}
+
 async fn handle_game_msg(
     msg: ClientGameMessage,
     manager: Manager,
@@ -124,7 +346,15 @@ async fn handle_game_msg(
     let result = match msg {
         ClientGameMessage::PlayTurn { card } => manager.play_turn(card, player_id).await,
         ClientGameMessage::PutBid { bid } => manager.bid(bid, player_id).await,
-        ClientGameMessage::Reconnect => manager.reconnect(player_id).await,
+        ClientGameMessage::Reconnect => manager.reconnect(player_id, None).await,
+        ClientGameMessage::Resume { lobby_id, last_seq } => {
+            manager
+                .reconnect(player_id, Some((lobby_id, last_seq)))
+                .await
+        }
+        ClientGameMessage::RequestHint => manager.hint(player_id).await,
+        ClientGameMessage::Leave => manager.leave(player_id).await,
+        ClientGameMessage::RematchVote { accept } => manager.rematch_vote(player_id, accept).await,
         ClientGameMessage::PlayerStatusChange { ready } => {
             manager.player_status_change(player_id, ready).await
         }
@@ -136,3 +366,27 @@ async fn handle_game_msg(
 
     Ok(result?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_heartbeat() {
+        let config = SessionConfig {
+            take_over: false,
+            ping_interval: Duration::from_millis(10),
+            pong_timeout: Duration::from_millis(100),
+        };
+        let mut heartbeat = Heartbeat::new(&config);
+
+        assert!(matches!(heartbeat.tick().await, Beat::Ping));
+
+        // a silent connection keeps getting pinged until the timeout runs out
+        while let Beat::Ping = heartbeat.tick().await {}
+        assert!(heartbeat.last_seen.elapsed() > config.pong_timeout);
+
+        heartbeat.seen();
+        assert!(matches!(heartbeat.tick().await, Beat::Ping));
+    }
+}
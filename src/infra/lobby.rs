@@ -4,46 +4,210 @@ use axum::{
     routing, Extension, Json, Router,
 };
 use reqwest::StatusCode;
+use strum::IntoEnumIterator;
 
 use crate::{
     models::GameError,
-    services::manager::{LobbyError, Manager},
+    services::manager::{
+        InviteDto, LobbyCredentials, LobbyError, LobbySettings, Manager, StreamParams,
+    },
 };
 
-use super::{auth::UserClaims, GetLobbyDto, JoinLobbyDto};
+use super::{auth::UserClaims, Emote, GetLobbyDto, JoinLobbyDto};
 
 pub fn router() -> Router<Manager> {
     Router::new()
         .route("/", routing::get(get_lobbies))
         .route("/", routing::post(create_lobby))
+        .route("/practice", routing::post(create_practice_lobby))
+        .route("/emotes", routing::get(get_emotes))
         .route("/:id", routing::put(join_lobby))
+        .route("/:id/players/me", routing::delete(leave_lobby))
+        .route("/:id/players/:player_id", routing::delete(kick_player))
+        .route("/:id/host", routing::put(transfer_host))
+        .route("/:id/settings", routing::put(update_settings))
+        .route("/:id/start", routing::post(start_lobby))
+        .route("/:id/spectators/me", routing::put(spectate))
+        .route("/:id/spectators/me", routing::delete(stop_spectating))
+        .route("/:id/stream/me", routing::put(watch_stream))
+        .route("/:id/stream/me", routing::delete(stop_watching_stream))
+        .route("/:id/invites", routing::post(create_invite))
+        .route("/:id/invites/:invite_id", routing::delete(revoke_invite))
 }
 
 async fn get_lobbies(State(manager): State<Manager>) -> Json<Vec<GetLobbyDto>> {
     Json(manager.get_lobbies().await)
 }
 
+async fn get_emotes() -> Json<Vec<Emote>> {
+    Json(Emote::iter().collect())
+}
+
 async fn join_lobby(
     State(manager): State<Manager>,
     Extension(user_claims): Extension<UserClaims>,
     Path(id): Path<String>,
+    credentials: Option<Json<LobbyCredentials>>,
+) -> Result<Json<JoinLobbyDto>, LobbyError> {
+    let credentials = credentials.map(|Json(c)| c).unwrap_or_default();
+
+    Ok(Json(
+        manager.join_lobby(id, user_claims, credentials).await?,
+    ))
+}
+
+async fn spectate(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+    credentials: Option<Json<LobbyCredentials>>,
 ) -> Result<Json<JoinLobbyDto>, LobbyError> {
-    let (players, should_reconnect) = manager.join_lobby(id.clone(), user_claims).await?;
+    let credentials = credentials.map(|Json(c)| c).unwrap_or_default();
+
+    Ok(Json(manager.spectate(id, user_claims, credentials).await?))
+}
+
+async fn stop_spectating(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, LobbyError> {
+    manager.stop_spectating(id, user_claims.id()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn watch_stream(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+    params: Option<Json<StreamParams>>,
+) -> Result<StatusCode, LobbyError> {
+    let params = params.map(|Json(p)| p).unwrap_or_default();
+
+    manager.watch_stream(id, user_claims.id(), params).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn stop_watching_stream(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, LobbyError> {
+    manager.stop_watching_stream(id, user_claims.id()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn leave_lobby(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, LobbyError> {
+    manager.leave_lobby(id, user_claims.id()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn kick_player(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path((id, player_id)): Path<(String, String)>,
+) -> Result<StatusCode, LobbyError> {
+    manager.kick_player(id, user_claims.id(), player_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn transfer_host(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+    Json(params): Json<TransferHostParams>,
+) -> Result<StatusCode, LobbyError> {
+    manager
+        .transfer_host(id, user_claims.id(), params.player_id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn update_settings(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+    Json(settings): Json<LobbySettings>,
+) -> Result<StatusCode, LobbyError> {
+    manager
+        .update_settings(id, user_claims.id(), settings)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn create_invite(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+) -> Result<Json<InviteDto>, LobbyError> {
+    Ok(Json(manager.create_invite(id, user_claims.id()).await?))
+}
+
+async fn revoke_invite(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path((id, invite_id)): Path<(String, String)>,
+) -> Result<StatusCode, LobbyError> {
+    manager
+        .revoke_invite(id, user_claims.id(), invite_id)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn start_lobby(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, LobbyError> {
+    manager.start_lobby(id, user_claims.id()).await?;
 
-    Ok(Json(JoinLobbyDto {
-        players,
-        id,
-        should_reconnect,
-    }))
+    Ok(StatusCode::NO_CONTENT)
 }
 
 async fn create_lobby(
     State(manager): State<Manager>,
     Extension(user_claims): Extension<UserClaims>,
-) -> Json<CreateLobbyResponse> {
-    let lobby_id = manager.create_lobby(user_claims.id()).await;
+    settings: Option<Json<LobbySettings>>,
+) -> Result<Json<CreateLobbyResponse>, LobbyError> {
+    let settings = settings.map(|Json(s)| s).unwrap_or_default();
+
+    let lobby_id = manager.create_lobby(user_claims.id(), settings).await?;
+
+    Ok(Json(CreateLobbyResponse { lobby_id }))
+}
+
+async fn create_practice_lobby(
+    State(manager): State<Manager>,
+    Extension(user_claims): Extension<UserClaims>,
+    Json(params): Json<PracticeParams>,
+) -> Result<Json<JoinLobbyDto>, LobbyError> {
+    let info = manager
+        .create_practice_lobby(user_claims, params.bots)
+        .await?;
+
+    Ok(Json(info))
+}
 
-    Json(CreateLobbyResponse { lobby_id })
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PracticeParams {
+    pub bots: usize,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct TransferHostParams {
+    pub player_id: String,
 }
 
 #[derive(serde::Serialize, serde::Deserialize)]
@@ -57,7 +221,27 @@ impl IntoResponse for LobbyError {
             LobbyError::InvalidLobby => StatusCode::NOT_FOUND,
             LobbyError::GameAlreadyStarted => StatusCode::BAD_REQUEST,
             LobbyError::GameNotStarted => StatusCode::BAD_REQUEST,
+            LobbyError::GameNotEnded => StatusCode::BAD_REQUEST,
             LobbyError::WrongLobby => StatusCode::BAD_REQUEST,
+            LobbyError::HintsDisabled => StatusCode::BAD_REQUEST,
+            LobbyError::NotYourTurn => StatusCode::BAD_REQUEST,
+            LobbyError::InvalidSettings(_) => StatusCode::BAD_REQUEST,
+            LobbyError::NotHost => StatusCode::FORBIDDEN,
+            LobbyError::PlayerNotFound => StatusCode::NOT_FOUND,
+            LobbyError::Kicked => StatusCode::FORBIDDEN,
+            LobbyError::SpectatorsFull => StatusCode::CONFLICT,
+            LobbyError::AlreadyInLobby => StatusCode::CONFLICT,
+            LobbyError::StreamDisabled => StatusCode::BAD_REQUEST,
+            LobbyError::InvalidChatMessage(_) => StatusCode::BAD_REQUEST,
+            LobbyError::ChatRateLimited => StatusCode::TOO_MANY_REQUESTS,
+            LobbyError::ReactionCooldown => StatusCode::TOO_MANY_REQUESTS,
+            LobbyError::TooManyLobbies => StatusCode::CONFLICT,
+            LobbyError::PrivateLobby => StatusCode::FORBIDDEN,
+            LobbyError::InvalidInvite => StatusCode::FORBIDDEN,
+            LobbyError::LobbyFull => StatusCode::CONFLICT,
+            LobbyError::AlreadyInGame => StatusCode::CONFLICT,
+            LobbyError::JoinInProgress => StatusCode::CONFLICT,
+            LobbyError::SimulationFailed => StatusCode::INTERNAL_SERVER_ERROR,
             LobbyError::GameError(e) => match e {
                 GameError::NotEnoughPlayers => StatusCode::BAD_REQUEST,
                 GameError::TooManyPlayers => StatusCode::BAD_REQUEST,
@@ -1,3 +1,4 @@
+pub mod analysis;
 pub mod auth;
 pub mod game;
 pub mod lobby;
@@ -6,10 +7,15 @@ use std::collections::HashMap;
 
 use auth::UserClaims;
 use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
 
 use crate::{
-    models::{Card, Turn},
-    services::{manager::PlayerStatus, GameInfoDto},
+    models::{bot::Hint, Card, Turn},
+    services::{
+        manager::{LobbySettings, PlayerStatus},
+        stream::StreamHands,
+        GameInfoDto, SpectatorInfoDto,
+    },
 };
 
 pub async fn fallback_handler() -> (StatusCode, &'static str) {
@@ -19,38 +25,198 @@ pub async fn fallback_handler() -> (StatusCode, &'static str) {
 const NOT_FOUND_RESPONSE: (StatusCode, &str) =
     (StatusCode::NOT_FOUND, "this resource doesn't exist");
 
+/// Clients that open with `Auth` instead of `Hello` predate the handshake and speak version 1
+pub const PROTOCOL_VERSION: u32 = 2;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+pub const BATCH: &str = "batch";
+pub const RESUME: &str = "resume";
+pub const MULTI_SESSION: &str = "multi_session";
+pub const ACK: &str = "ack";
+
+/// Optional protocol behaviours a client may ask for, only the ones listed here are accepted
+pub const CAPABILITIES: [&str; 4] = [BATCH, RESUME, MULTI_SESSION, ACK];
+
+/// What a connection agreed on during the handshake
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Protocol {
+    pub version: u32,
+    pub capabilities: Vec<String>,
+}
+
+impl Protocol {
+    /// Clients that skip `Hello` get the plain version 1 wire format
+    pub fn legacy() -> Self {
+        Self {
+            version: MIN_PROTOCOL_VERSION,
+            capabilities: Vec::new(),
+        }
+    }
+
+    /// Capabilities only exist from version 2 on, and `resume` is useless without the
+    /// sequence numbers that come with `batch`
+    pub fn negotiate(version: u32, requested: Vec<String>) -> Self {
+        let version = version.min(PROTOCOL_VERSION);
+
+        if version < 2 {
+            return Self {
+                version,
+                ..Self::legacy()
+            };
+        }
+
+        let mut capabilities: Vec<_> = CAPABILITIES
+            .into_iter()
+            .filter(|c| requested.iter().any(|r| r == c))
+            .map(String::from)
+            .collect();
+
+        if !capabilities.iter().any(|c| c == BATCH) {
+            capabilities.retain(|c| c != RESUME);
+        }
+
+        Self {
+            version,
+            capabilities,
+        }
+    }
+
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities.iter().any(|c| c == capability)
+    }
+}
+
 #[derive(serde::Deserialize, serde::Serialize, Debug)]
 #[serde(tag = "type", content = "data")]
 pub enum ClientMessage {
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        capabilities: Vec<String>,
+    },
     Game(ClientGameMessage),
-    Auth { token: String },
+    Auth {
+        token: String,
+    },
+    Chat {
+        text: String,
+    },
+    React {
+        emote: Emote,
+    },
+    /// Wraps another message so the server answers it with `Ack` or `Nack`,
+    /// a repeated id gets the first answer again without running the message twice
+    Request {
+        id: String,
+        message: Box<ClientMessage>,
+    },
+}
+
+#[derive(
+    serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq, strum_macros::EnumIter,
+)]
+pub enum Emote {
+    Nice,
+    GoodGame,
+    WellPlayed,
+    Oops,
+    Wow,
+    Thinking,
+    Laugh,
+    Hurry,
+}
+
+#[derive(
+    serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq, strum_macros::EnumIter,
+)]
+pub enum Feature {
+    Hints,
+    TurnTimer,
+    RotateSeats,
+    Spectators,
+    Stream,
+    PrivateLobbies,
+    Practice,
+    Rematch,
+    Chat,
+    Reactions,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct WelcomeDto {
+    pub protocol_version: u32,
+    pub capabilities: Vec<String>,
+    pub features: Vec<Feature>,
+    pub limits: ServerLimits,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ServerLimits {
+    pub max_players: usize,
+    pub max_owned_lobbies: usize,
+    pub max_spectators: usize,
+    pub min_turn_seconds: u64,
+    pub min_stream_delay_seconds: u64,
+    pub max_stream_delay_seconds: u64,
+    pub max_chat_length: usize,
+    pub chat_rate_limit: usize,
+    pub chat_rate_window_seconds: u64,
+    pub reaction_cooldown_seconds: u64,
+    pub replay_buffer_size: usize,
+    pub ping_interval_seconds: u64,
+    pub pong_timeout_seconds: u64,
 }
 
-#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug)]
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug)]
 #[serde(tag = "type", content = "data")]
 pub enum ClientGameMessage {
     PlayTurn { card: Card },
     PutBid { bid: usize },
     PlayerStatusChange { ready: bool },
     Reconnect,
+    Resume { lobby_id: String, last_seq: u64 },
+    RequestHint,
+    Leave,
+    RematchVote { accept: bool },
 }
 
 #[derive(serde::Serialize)]
 pub struct GetLobbyDto {
     pub id: String,
+    pub host: String,
+    pub creator: String,
+    pub created_at: DateTime<Utc>,
     pub player_count: usize,
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct JoinLobbyDto {
     pub id: String,
+    pub host: String,
     pub players: Vec<PlayerStatus>,
+    pub settings: LobbySettings,
+    pub spectators: usize,
     pub should_reconnect: bool,
 }
 
 pub type PlayerPoints = HashMap<String, usize>;
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+/// Every message produced by one lobby action, in order. `seq` grows by one for each batch
+/// the whole lobby receives, private batches (decks, hints, snapshots) repeat the last one
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct EventBatch {
+    pub lobby_id: String,
+    pub seq: u64,
+    pub events: Vec<ServerMessage>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LobbyClosedReason {
+    GameFinished,
+    Idle,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 #[serde(tag = "type", content = "data")]
 pub enum ServerMessage {
     PlayerTurn {
@@ -84,8 +250,91 @@ pub enum ServerMessage {
         lifes: PlayerPoints,
     },
     PlayerJoined(UserClaims),
+    PlayerLeft {
+        player_id: String,
+    },
+    PlayerKicked {
+        player_id: String,
+    },
+    HostChanged {
+        player_id: String,
+    },
+    SettingsChanged(LobbySettings),
+    LobbyClosed {
+        lobby_id: String,
+        reason: LobbyClosedReason,
+    },
+    RematchVote {
+        player_id: String,
+        accept: bool,
+    },
+    RematchStarted {
+        players: Vec<PlayerStatus>,
+    },
+    SpectatorJoined(UserClaims),
+    SpectatorLeft {
+        player_id: String,
+    },
+    SpectatorReconnect(SpectatorInfoDto),
+    StreamHands(StreamHands),
+    Chat {
+        player_id: String,
+        text: String,
+        time: DateTime<Utc>,
+    },
+    Reaction {
+        player_id: String,
+        emote: Emote,
+    },
+    PlayerDisconnected {
+        player_id: String,
+    },
+    PlayerReconnected {
+        player_id: String,
+    },
+    BotTakeover {
+        player_id: String,
+    },
+    TurnTimer {
+        player_id: String,
+        remaining: u64,
+    },
     Reconnect(GameInfoDto),
+    Hint(Hint),
+    Batch(EventBatch),
+    Welcome(WelcomeDto),
+    Ack {
+        id: String,
+    },
+    Nack {
+        id: String,
+        code: String,
+    },
     Error {
         msg: String,
     },
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn requested(capabilities: &[&str]) -> Vec<String> {
+        capabilities.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn test_negotiate() {
+        let protocol =
+            Protocol::negotiate(PROTOCOL_VERSION + 1, requested(&[ACK, "unknown", BATCH]));
+        assert_eq!(protocol.version, PROTOCOL_VERSION);
+        assert_eq!(protocol.capabilities, requested(&[BATCH, ACK]));
+
+        let protocol = Protocol::negotiate(1, requested(&CAPABILITIES));
+        assert_eq!(protocol, Protocol::legacy());
+
+        let protocol = Protocol::negotiate(2, requested(&[RESUME, MULTI_SESSION]));
+        assert!(!protocol.supports(RESUME));
+        assert!(protocol.supports(MULTI_SESSION));
+    }
+}
@@ -7,7 +7,7 @@ use std::net::{Ipv4Addr, SocketAddr};
 use axum::{routing, Router};
 use infra::auth::JWT_KEY;
 use services::{
-    manager::Manager,
+    manager::{Manager, ReaperConfig, SessionConfig},
     repositories::{auth::AuthRepository, game::GamesRepository, get_mongo_client},
 };
 
@@ -33,7 +33,13 @@ pub async fn start_app() {
         .expect("Expected to create mongo client")
         .database("oh_hell");
 
-    let manager = Manager::new(GamesRepository::new(&db), AuthRepository::new(&db));
+    let manager = Manager::new(
+        GamesRepository::new(&db),
+        AuthRepository::new(&db),
+        SessionConfig::from_env(),
+    );
+
+    manager.start_reaper(ReaperConfig::from_env());
 
     let auth_layer = axum::middleware::from_fn(infra::auth::middleware);
 
@@ -47,8 +53,9 @@ pub async fn start_app() {
 
     let app = Router::new()
         .route("/game", routing::get(infra::game::ws_handler))
-        .nest("/lobby", infra::lobby::router().layer(auth_layer))
+        .nest("/lobby", infra::lobby::router().layer(auth_layer.clone()))
         .nest("/auth", infra::auth::router())
+        .nest("/analysis", infra::analysis::router().layer(auth_layer))
         .fallback(infra::fallback_handler)
         .with_state(manager)
         .layer(tower_http::trace::TraceLayer::new_for_http())
@@ -0,0 +1,153 @@
+use rand::seq::SliceRandom;
+
+use super::Card;
+
+const SIMULATIONS: usize = 1000;
+
+/// Estimates how many tricks `hand` should take by simulating the set against random opponent hands.
+///
+/// `seat` is the position in the playing order of the first round, `0` being the first to play.
+pub fn expected_tricks(hand: &[Card], upcard: Card, player_count: usize, seat: usize) -> f64 {
+    let unknown: Vec<_> = Card::deck()
+        .into_iter()
+        .filter(|c| *c != upcard && !hand.contains(c))
+        .collect();
+
+    let mut rng = rand::thread_rng();
+
+    let won: usize = (0..SIMULATIONS)
+        .map(|_| {
+            let mut deck = unknown.clone();
+            deck.shuffle(&mut rng);
+
+            let mut hands: Vec<_> = (0..player_count)
+                .map(|s| match s == seat {
+                    true => hand.to_vec(),
+                    false => deck.drain(..hand.len()).collect(),
+                })
+                .collect();
+
+            simulate_set(&mut hands, upcard, seat)
+        })
+        .sum();
+
+    won as f64 / SIMULATIONS as f64
+}
+
+/// Picks the legal bid closest to the expected number of tricks.
+pub fn recommend_bid(expected: f64, possible_bids: &[usize]) -> Option<usize> {
+    possible_bids.iter().copied().min_by(|&a, &b| {
+        let a = (a as f64 - expected).abs();
+        let b = (b as f64 - expected).abs();
+        a.total_cmp(&b)
+    })
+}
+
+/// Picks a card to play given the strongest card already in the pile.
+///
+/// When `wants_trick` is set it leads with the strongest card or beats the pile as cheaply as possible,
+/// otherwise it gets rid of the strongest card that still loses.
+pub fn pick_card(
+    hand: &[Card],
+    upcard: Card,
+    best: Option<u16>,
+    wants_trick: bool,
+) -> Option<Card> {
+    let mut cards = hand.to_vec();
+    cards.sort_by_key(|c| c.get_strength(upcard));
+
+    let beats = |c: &&Card| best.is_none_or(|b| c.get_strength(upcard) > b);
+
+    let card = match (wants_trick, best) {
+        (true, None) => cards.last(),
+        (true, Some(_)) => cards.iter().find(beats).or(cards.first()),
+        (false, _) => cards.iter().rev().find(|c| !beats(c)).or(cards.first()),
+    };
+
+    card.copied()
+}
+
+fn simulate_set(hands: &mut [Vec<Card>], upcard: Card, seat: usize) -> usize {
+    let mut leader = 0;
+    let mut won = 0;
+
+    while !hands[seat].is_empty() {
+        let mut best: Option<(u16, usize)> = None;
+
+        for offset in 0..hands.len() {
+            let s = (leader + offset) % hands.len();
+
+            let card = pick_card(&hands[s], upcard, best.map(|(v, _)| v), true)
+                .expect("Every player should have a card left");
+
+            hands[s].retain(|&c| c != card);
+
+            let value = card.get_strength(upcard);
+
+            if best.is_none_or(|(b, _)| value > b) {
+                best = Some((value, s));
+            }
+        }
+
+        let (_, winner) = best.expect("Should contain a winner");
+
+        if winner == seat {
+            won += 1;
+        }
+
+        leader = winner;
+    }
+
+    won
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{Rank, Suit};
+
+    use super::*;
+
+    #[test]
+    fn test_expected_tricks() {
+        let upcard = Card::new(Rank::Seven, Suit::Golds);
+
+        let manilha = Card::new(Rank::Ten, Suit::Clubs);
+        assert_eq!(expected_tricks(&[manilha], upcard, 4, 2), 1.0);
+
+        let weakest = Card::new(Rank::Four, Suit::Golds);
+        assert_eq!(expected_tricks(&[weakest], upcard, 4, 0), 0.0);
+
+        assert_eq!(expected_tricks(&[manilha, weakest], upcard, 3, 1), 1.0);
+    }
+
+    #[test]
+    fn test_recommend_bid() {
+        assert_eq!(recommend_bid(1.4, &[0, 1, 2]), Some(1));
+        assert_eq!(recommend_bid(1.6, &[0, 1, 2]), Some(2));
+        assert_eq!(recommend_bid(1.2, &[0, 2, 3]), Some(2));
+        assert_eq!(recommend_bid(0.0, &[]), None);
+    }
+
+    #[test]
+    fn test_pick_card() {
+        let upcard = Card::new(Rank::Seven, Suit::Golds);
+
+        let hand = [
+            Card::new(Rank::Four, Suit::Golds),
+            Card::new(Rank::Twelve, Suit::Cups),
+            Card::new(Rank::Three, Suit::Clubs),
+        ];
+
+        let strength = |c: Card| Some(c.get_strength(upcard));
+
+        assert_eq!(pick_card(&hand, upcard, None, true), Some(hand[2]));
+        assert_eq!(pick_card(&hand, upcard, None, false), Some(hand[0]));
+
+        let best = strength(Card::new(Rank::Eleven, Suit::Swords));
+        assert_eq!(pick_card(&hand, upcard, best, true), Some(hand[1]));
+        assert_eq!(pick_card(&hand, upcard, best, false), Some(hand[0]));
+
+        let best = strength(Card::new(Rank::One, Suit::Swords));
+        assert_eq!(pick_card(&hand, upcard, best, false), Some(hand[1]));
+    }
+}
@@ -0,0 +1,213 @@
+use crate::services::{GameInfoDto, GameStageDto};
+
+use super::{analysis, Card};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum BotAction {
+    Bid(usize),
+    Play(Card),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Hint {
+    pub action: BotAction,
+    pub reason: String,
+}
+
+/// Chooses the next action for `player_id` using only what this player can see of the game.
+pub fn choose_action(info: &GameInfoDto, player_id: &str) -> Option<BotAction> {
+    suggest(info, player_id).map(|h| h.action)
+}
+
+/// Same as [`choose_action`] but explains the choice, so it can be shown to players.
+pub fn suggest(info: &GameInfoDto, player_id: &str) -> Option<Hint> {
+    match &info.stage {
+        GameStageDto::Bidding { possible_bids } => {
+            let seat = info.info.iter().filter(|p| p.bid.is_some()).count();
+
+            let expected =
+                analysis::expected_tricks(&info.deck, info.upcard, info.info.len(), seat);
+
+            let bid = analysis::recommend_bid(expected, possible_bids)?;
+
+            Some(Hint {
+                action: BotAction::Bid(bid),
+                reason: format!("Your hand should take about {expected:.1} tricks"),
+            })
+        }
+        GameStageDto::Dealing => {
+            let player = info.info.iter().find(|p| p.id == player_id)?;
+
+            let wants_trick = player.rounds < player.bid.unwrap_or_default();
+
+            let best = info
+                .pile
+                .iter()
+                .map(|t| t.card.get_strength(info.upcard))
+                .max();
+
+            let card = analysis::pick_card(&info.deck, info.upcard, best, wants_trick)?;
+
+            let wins = best.is_none_or(|b| card.get_strength(info.upcard) > b);
+
+            let reason = match (wants_trick, best.is_some(), wins) {
+                (true, false, _) => "You still need tricks, lead with your strongest card",
+                (true, true, true) => "Cheapest card that wins this trick",
+                (true, true, false) => "You can't win this trick, save your stronger cards",
+                (false, false, _) => "You don't need more tricks, lead with your weakest card",
+                (false, true, false) => "Strongest card that still loses this trick",
+                (false, true, true) => "You can't avoid winning, get rid of your weakest card",
+            };
+
+            Some(Hint {
+                action: BotAction::Play(card),
+                reason: reason.to_string(),
+            })
+        }
+    }
+}
+
+/// Plays without any strategy: the lowest legal bid or the weakest card.
+pub fn fallback_action(info: &GameInfoDto) -> Option<BotAction> {
+    match &info.stage {
+        GameStageDto::Bidding { possible_bids } => {
+            possible_bids.iter().min().copied().map(BotAction::Bid)
+        }
+        GameStageDto::Dealing => info
+            .deck
+            .iter()
+            .min_by_key(|c| c.get_strength(info.upcard))
+            .copied()
+            .map(BotAction::Play),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        models::{Game, Rank, Suit, Turn},
+        services::PlayerInfoDto,
+    };
+
+    use super::*;
+
+    fn info(deck: &[Card], pile: &[Card], bid: Option<usize>, stage: GameStageDto) -> GameInfoDto {
+        GameInfoDto {
+            info: vec![PlayerInfoDto {
+                id: "P1".to_string(),
+                lifes: 5,
+                rounds: 0,
+                bid,
+                online: true,
+            }],
+            deck: deck.to_vec(),
+            upcard: Card::new(Rank::Seven, Suit::Golds),
+            pile: pile
+                .iter()
+                .map(|&card| Turn {
+                    player_id: "P2".to_string(),
+                    card,
+                })
+                .collect(),
+            current_player: Some("P1".to_string()),
+            stage,
+        }
+    }
+
+    fn assert_hint(deck: &[Card], pile: &[Card], bid: usize, card: Card, reason: &str) {
+        let hint = suggest(&info(deck, pile, Some(bid), GameStageDto::Dealing), "P1").unwrap();
+
+        assert_eq!(hint.action, BotAction::Play(card));
+        assert_eq!(hint.reason, reason);
+    }
+
+    #[test]
+    fn test_bot_game() {
+        let players: Vec<_> = (0..4).map(|i| i.to_string()).collect();
+
+        let mut game = Game::new(players, 3).unwrap();
+
+        while let Some(player) = game.get_current_player() {
+            let info = game.get_game_info(&player);
+
+            match choose_action(&info, &player).unwrap() {
+                BotAction::Bid(bid) => {
+                    game.bid(&player, bid).unwrap();
+                }
+                BotAction::Play(card) => {
+                    let turn = Turn {
+                        player_id: player,
+                        card,
+                    };
+
+                    game.deal(turn).unwrap();
+                }
+            }
+        }
+
+        assert!(game.is_ended());
+    }
+
+    #[test]
+    fn test_suggest() {
+        let manilha = Card::new(Rank::Ten, Suit::Clubs);
+
+        let bidding = GameStageDto::Bidding {
+            possible_bids: vec![0, 1],
+        };
+        let hint = suggest(&info(&[manilha], &[], None, bidding), "P1").unwrap();
+        assert_eq!(hint.action, BotAction::Bid(1));
+        assert_eq!(hint.reason, "Your hand should take about 1.0 tricks");
+
+        let four = Card::new(Rank::Four, Suit::Golds);
+        let twelve = Card::new(Rank::Twelve, Suit::Cups);
+        let three = Card::new(Rank::Three, Suit::Clubs);
+        let hand = [four, twelve, three];
+
+        let reason = "You still need tricks, lead with your strongest card";
+        assert_hint(&hand, &[], 1, three, reason);
+
+        let eleven = Card::new(Rank::Eleven, Suit::Swords);
+        assert_hint(
+            &hand,
+            &[eleven],
+            1,
+            twelve,
+            "Cheapest card that wins this trick",
+        );
+
+        let reason = "You can't win this trick, save your stronger cards";
+        assert_hint(&hand, &[manilha], 1, four, reason);
+
+        let reason = "You don't need more tricks, lead with your weakest card";
+        assert_hint(&hand, &[], 0, four, reason);
+
+        let one = Card::new(Rank::One, Suit::Swords);
+        let reason = "Strongest card that still loses this trick";
+        assert_hint(&hand, &[one], 0, twelve, reason);
+
+        let reason = "You can't avoid winning, get rid of your weakest card";
+        assert_hint(&[twelve, three], &[four], 0, twelve, reason);
+    }
+
+    #[test]
+    fn test_fallback_action() {
+        let bidding = GameStageDto::Bidding {
+            possible_bids: vec![2, 1, 3],
+        };
+        let action = fallback_action(&info(&[], &[], None, bidding));
+        assert_eq!(action, Some(BotAction::Bid(1)));
+
+        let hand = [
+            Card::new(Rank::Three, Suit::Clubs),
+            Card::new(Rank::Ten, Suit::Golds),
+            Card::new(Rank::Four, Suit::Cups),
+        ];
+        let action = fallback_action(&info(&hand, &[], Some(1), GameStageDto::Dealing));
+        assert_eq!(action, Some(BotAction::Play(hand[2])));
+
+        let action = fallback_action(&info(&[], &[], Some(1), GameStageDto::Dealing));
+        assert_eq!(action, None);
+    }
+}
@@ -4,12 +4,12 @@ use indexmap::IndexMap;
 
 use crate::{
     models::GameError,
-    services::{GameInfoDto, GameStageDto, PlayerInfoDto},
+    services::{GameInfoDto, GameStageDto, PlayerInfoDto, SpectatorInfoDto},
 };
 
 use super::{
-    iter::CyclicIterator, BiddingError, BiddingState, Card, DealState, DealingMode, GameEvent,
-    Player, Turn, TurnError,
+    iter::CyclicIterator, BiddingError, BiddingState, Card, DealState, DealingMode, ForfeitState,
+    GameEvent, Player, Turn, TurnError,
 };
 
 #[derive(Debug)]
@@ -81,6 +81,79 @@ impl Game {
         self.pile.push((self.get_card_value(turn.card), turn));
         self.round_iter.next();
 
+        Ok(self.resolve_turn())
+    }
+
+    pub fn forfeit(&mut self, player_id: &str) -> Result<ForfeitState, TurnError> {
+        if self.is_ended() {
+            return Err(TurnError::GameEnded);
+        }
+
+        let idx = self
+            .players
+            .get_index_of(player_id)
+            .ok_or(TurnError::InvalidPlayer)?;
+
+        if !self.players[idx].is_alive() {
+            return Err(TurnError::InvalidPlayer);
+        }
+
+        let stage = self.get_stage();
+
+        let player = &mut self.players[idx];
+        player.lifes = 0;
+        player.deck.clear();
+        player.bid = None;
+        player.rounds = 0;
+
+        self.round_iter.remove(idx);
+        self.bidding_iter.remove(idx);
+        self.pile.retain(|(_, t)| t.player_id != player_id);
+
+        if self.is_ended() {
+            self.pile.clear();
+
+            return Ok(ForfeitState::Ended {
+                winner: self.alive_players().next().map(|(id, _)| id.clone()),
+                lifes: self.get_lifes(),
+            });
+        }
+
+        let state = match stage {
+            GameStage::Bidding => {
+                let state = match self.peek_current_bidder() {
+                    Some(next) => BiddingState::Active {
+                        next,
+                        possible_bids: self.get_possible_bids(),
+                    },
+                    None => {
+                        self.bidding_iter.shift();
+                        let next = self.peek_current_dealer().expect("Should have a dealer");
+                        BiddingState::Ended { next }
+                    }
+                };
+
+                ForfeitState::Bidding(state)
+            }
+            GameStage::Dealing => {
+                let state = match self.pile.is_empty() {
+                    true => DealState {
+                        event: GameEvent::TurnPlayed {
+                            next: self.peek_current_dealer().expect("Should have a dealer"),
+                        },
+                        pile: vec![],
+                    },
+                    false => self.resolve_turn(),
+                };
+
+                ForfeitState::Dealing(state)
+            }
+        };
+
+        Ok(state)
+    }
+
+    fn resolve_turn(&mut self) -> DealState {
         //finish set/game
         if self.alive_players().all(|(_, p)| p.deck.is_empty()) {
             let pile = self.award_points();
@@ -113,7 +186,7 @@ impl Game {
                 }
             };
 
-            return Ok(DealState { event, pile });
+            return DealState { event, pile };
         }
 
         //finish round
@@ -134,17 +207,17 @@ impl Game {
                 rounds: self.get_points(),
             };
 
-            return Ok(DealState { event, pile });
+            return DealState { event, pile };
         }
 
         let event = GameEvent::TurnPlayed {
             next: self.peek_current_dealer().expect("Should have a dealer"),
         };
 
-        Ok(DealState {
+        DealState {
             pile: self.get_pile(),
             event,
-        })
+        }
     }
 
     pub fn bid(&mut self, player_id: &String, bid: usize) -> Result<BiddingState, BiddingError> {
@@ -232,34 +305,70 @@ impl Game {
 
         let deck = player.deck.clone();
 
-        let info = self
+        GameInfoDto {
+            deck,
+            upcard: self.upcard,
+            info: self.get_players_info(),
+            pile: self.get_pile(),
+            current_player: self.get_current_player(),
+            stage: self.get_stage_dto(),
+        }
+    }
+
+    pub fn get_spectator_info(&self) -> SpectatorInfoDto {
+        let deck_sizes = self
             .alive_players()
+            .map(|(id, p)| (id.clone(), p.deck.len()))
+            .collect();
+
+        SpectatorInfoDto {
+            info: self.get_players_info(),
+            deck_sizes,
+            upcard: self.upcard,
+            pile: self.get_pile(),
+            current_player: self.get_current_player(),
+            stage: self.get_stage_dto(),
+        }
+    }
+
+    fn get_players_info(&self) -> Vec<PlayerInfoDto> {
+        self.alive_players()
             .map(|(id, p)| PlayerInfoDto {
                 id: id.clone(),
                 lifes: p.lifes,
                 bid: p.bid,
                 rounds: p.rounds,
+                online: true,
             })
-            .collect();
+            .collect()
+    }
 
-        let current_player = match self.get_stage() {
-            GameStage::Dealing => self.peek_current_dealer(),
-            GameStage::Bidding => self.peek_current_bidder(),
+    fn get_stage_dto(&self) -> GameStageDto {
+        match self.get_stage() {
+            GameStage::Dealing => GameStageDto::Dealing,
+            GameStage::Bidding => GameStageDto::Bidding {
+                possible_bids: self.get_possible_bids(),
+            },
         }
-        .expect("Should contain an active player")
-        .to_string();
+    }
 
-        GameInfoDto {
-            deck,
-            upcard: self.upcard,
-            info,
-            current_player,
-            stage: match self.get_stage() {
-                GameStage::Dealing => GameStageDto::Dealing,
-                GameStage::Bidding => GameStageDto::Bidding {
-                    possible_bids: self.get_possible_bids(),
-                },
-            },
+    pub fn get_current_player(&self) -> Option<String> {
+        match self.is_ended() {
+            true => None,
+            false => self.peek_current_player(),
+        }
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.alive_players().count() < 2
+    }
+
+    pub fn get_winner(&self) -> Option<String> {
+        let mut alive = self.alive_players();
+
+        match (alive.next(), alive.next()) {
+            (Some((id, _)), None) => Some(id.clone()),
+            _ => None,
         }
     }
 
@@ -393,7 +502,12 @@ impl Game {
             .collect()
     }
 
-    fn get_lifes(&self) -> HashMap<String, usize> {
+    /// Everyone who was dealt into the game, including eliminated and forfeited players
+    pub fn get_players_id(&self) -> Vec<String> {
+        self.players.keys().cloned().collect()
+    }
+
+    pub fn get_lifes(&self) -> HashMap<String, usize> {
         self.players
             .iter()
             .map(|(id, player)| (id.clone(), player.lifes))
@@ -401,13 +515,7 @@ impl Game {
     }
 
     fn get_card_value(&self, card: Card) -> u16 {
-        let card_value = card.get_value() as u16;
-
-        if self.upcard.rank.get_next() == card.rank {
-            card_value + 100
-        } else {
-            card_value
-        }
+        card.get_strength(self.upcard)
     }
 
     fn peek_current_dealer(&self) -> Option<String> {
@@ -425,6 +533,13 @@ impl Game {
         }
     }
 
+    fn peek_current_player(&self) -> Option<String> {
+        match self.get_stage() {
+            GameStage::Dealing => self.peek_current_dealer(),
+            GameStage::Bidding => self.peek_current_bidder(),
+        }
+    }
+
     fn peek_current_bidder(&self) -> Option<String> {
         self.bidding_iter.peek().map(|i| self.get_player(i))
     }
@@ -579,6 +694,123 @@ mod tests {
         assert_eq!(possible, vec![1, 2, 3]);
     }
 
+    #[test]
+    fn test_forfeit_bidding() {
+        let [p1, p2, p3] = ["P1", "P2", "P3"].map(String::from);
+
+        let mut game = Game::new_default(vec![p1.clone(), p2.clone(), p3.clone()]).unwrap();
+
+        game.bid(&p1, 0).unwrap();
+
+        let state = game.forfeit(&p2).unwrap();
+        assert!(matches!(
+            state,
+            ForfeitState::Bidding(BiddingState::Active { next, possible_bids })
+                if next == p3 && possible_bids == vec![0]
+        ));
+
+        let state = game.bid(&p3, 0).unwrap();
+        assert!(matches!(state, BiddingState::Ended { next } if next == p1));
+
+        let card = game.players[&p1].deck[0];
+        game.deal(Turn {
+            player_id: p1.clone(),
+            card,
+        })
+        .unwrap();
+
+        let state = game.forfeit(&p3).unwrap();
+        assert!(matches!(state, ForfeitState::Ended { winner, lifes: _ } if winner == Some(p1)));
+        assert!(game.is_ended());
+    }
+
+    #[test]
+    fn test_get_winner() {
+        let [p1, p2] = ["P1", "P2"].map(String::from);
+
+        let mut game = Game::new_default(vec![p1.clone(), p2.clone()]).unwrap();
+        assert_eq!(game.get_winner(), None);
+
+        game.forfeit(&p2).unwrap();
+        assert_eq!(game.get_winner(), Some(p1));
+        assert_eq!(game.get_lifes()[&p2], 0);
+    }
+
+    #[test]
+    fn test_forfeit_ended() {
+        let [p1, p2] = ["P1", "P2"].map(String::from);
+
+        let mut game = Game::new_default(vec![p1.clone(), p2.clone()]).unwrap();
+        game.forfeit(&p2).unwrap();
+
+        assert!(matches!(game.forfeit(&p1), Err(TurnError::GameEnded)));
+        assert_eq!(game.get_lifes()[&p1], 5);
+        assert_eq!(game.get_winner(), Some(p1));
+    }
+
+    #[test]
+    fn test_game_info_after_forfeit() {
+        let [p1, p2] = ["P1", "P2"].map(String::from);
+
+        let mut game = Game::new_default(vec![p1.clone(), p2.clone()]).unwrap();
+
+        game.bid(&p1, 0).unwrap();
+        game.bid(&p2, 0).unwrap();
+
+        let card = game.players[&p1].deck[0];
+        game.deal(Turn {
+            player_id: p1.clone(),
+            card,
+        })
+        .unwrap();
+
+        game.forfeit(&p2).unwrap();
+
+        assert_eq!(game.get_game_info(&p1).current_player, None);
+    }
+
+    #[test]
+    fn test_forfeit_dealing() {
+        let [p1, p2, p3] = ["P1", "P2", "P3"].map(String::from);
+
+        let mut game = Game::new_default(vec![p1.clone(), p2.clone(), p3.clone()]).unwrap();
+
+        for p in [&p1, &p2, &p3] {
+            game.bid(p, 0).unwrap();
+        }
+
+        let card = game.players[&p1].deck[0];
+        game.deal(Turn {
+            player_id: p1.clone(),
+            card,
+        })
+        .unwrap();
+
+        let state = game.forfeit(&p1).unwrap();
+        assert!(matches!(
+            state,
+            ForfeitState::Dealing(DealState { event: GameEvent::TurnPlayed { next }, pile })
+                if next == p2 && pile.is_empty()
+        ));
+
+        for p in [&p2, &p3] {
+            let card = game.players[p].deck[0];
+            let state = game
+                .deal(Turn {
+                    player_id: p.clone(),
+                    card,
+                })
+                .unwrap();
+
+            if p == &p3 {
+                assert!(matches!(state.event, GameEvent::SetEnded { .. }));
+            }
+        }
+
+        assert_eq!(game.get_decks().0.len(), 2);
+        assert!(game.forfeit(&p1).is_err());
+    }
+
     #[test]
     fn test_card_mode() {
         assert_eq!(
@@ -39,6 +39,11 @@ impl CyclicIterator {
     pub fn remove(&mut self, item: usize) -> Option<usize> {
         let idx = self.items.iter().position(|&i| i == item)?;
         self.items.remove(idx);
+
+        if idx < self.current_index {
+            self.current_index -= 1;
+        }
+
         Some(idx)
     }
 
@@ -154,4 +159,21 @@ mod tests {
         assert_eq!(result, vec![3, 1]);
         cyclic.reset();
     }
+
+    #[test]
+    fn test_remove_visited() {
+        let mut cyclic = CyclicIterator::new(4);
+
+        assert_eq!(cyclic.next(), Some(0));
+        assert_eq!(cyclic.next(), Some(1));
+
+        cyclic.remove(0);
+        assert_eq!(cyclic.peek(), Some(2));
+
+        cyclic.remove(2);
+        assert_eq!(cyclic.peek(), Some(3));
+
+        cyclic.remove(3);
+        assert_eq!(cyclic.peek(), None);
+    }
 }
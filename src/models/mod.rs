@@ -1,3 +1,5 @@
+pub mod analysis;
+pub mod bot;
 mod game;
 pub mod iter;
 
@@ -10,7 +12,7 @@ use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 
 use rand::seq::SliceRandom;
-use strum_macros::{Display, EnumIter};
+use strum_macros::{Display, EnumIter, EnumString, IntoStaticStr};
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq, Eq)]
 pub struct Turn {
@@ -84,6 +86,16 @@ impl Card {
         deck
     }
 
+    pub fn get_strength(&self, upcard: Card) -> u16 {
+        let value = self.get_value() as u16;
+
+        if upcard.rank.get_next() == self.rank {
+            value + 100
+        } else {
+            value
+        }
+    }
+
     fn get_value(&self) -> u8 {
         let rank = self.rank as u8 * 10;
         let suit = self.suit as u8;
@@ -91,7 +103,9 @@ impl Card {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, EnumIter, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Serialize, Deserialize, EnumIter, EnumString, Clone, Copy, PartialEq, PartialOrd, Eq, Ord,
+)]
 pub enum Rank {
     Four,
     Five,
@@ -124,7 +138,9 @@ impl Rank {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, EnumIter, Clone, Copy, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(
+    Debug, Serialize, Deserialize, EnumIter, EnumString, Clone, Copy, PartialEq, PartialOrd, Eq, Ord,
+)]
 pub enum Suit {
     Golds,
     Swords,
@@ -165,6 +181,15 @@ pub struct DealState {
     pub pile: Vec<Turn>,
 }
 
+pub enum ForfeitState {
+    Bidding(BiddingState),
+    Dealing(DealState),
+    Ended {
+        winner: Option<String>,
+        lifes: HashMap<String, usize>,
+    },
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum BiddingState {
     Active {
@@ -182,7 +207,7 @@ enum DealingMode {
     Decreasing,
 }
 
-#[derive(thiserror::Error, Debug)]
+#[derive(thiserror::Error, Debug, IntoStaticStr)]
 pub enum GameError {
     #[error("Not enough players")]
     NotEnoughPlayers,
@@ -194,7 +219,7 @@ pub enum GameError {
     InvalidBid(#[from] BiddingError),
 }
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, thiserror::Error, IntoStaticStr)]
 pub enum TurnError {
     #[error("BiddingStageActive")]
     BiddingStageActive,
@@ -204,9 +229,11 @@ pub enum TurnError {
     NotYourCard,
     #[error("InvalidPlayer")]
     InvalidPlayer,
+    #[error("GameEnded")]
+    GameEnded,
 }
 
-#[derive(Debug, thiserror::Error, Display, PartialEq, Eq)]
+#[derive(Debug, thiserror::Error, Display, IntoStaticStr, PartialEq, Eq)]
 pub enum BiddingError {
     InvalidPlayer,
     AlreadyBidded,
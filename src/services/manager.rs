@@ -1,22 +1,73 @@
 use std::{
     borrow::{BorrowMut, Cow},
-    collections::{HashMap, HashSet},
-    sync::Arc,
+    collections::{HashMap, HashSet, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
 use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use chrono::{DateTime, Utc};
 use futures::{stream::SplitSink, SinkExt};
 use indexmap::IndexMap;
-use tokio::sync::Mutex;
+use strum::IntoEnumIterator;
+use tokio::{
+    sync::{
+        mpsc::{self, error::TrySendError, UnboundedSender},
+        oneshot, watch, Mutex,
+    },
+    task::{AbortHandle, JoinHandle},
+};
 
 use crate::{
-    infra::{self, auth::UserClaims, GetLobbyDto, ServerMessage},
+    infra::{
+        self,
+        auth::{self, BotClaims, UserClaims},
+        Emote, EventBatch, Feature, GetLobbyDto, JoinLobbyDto, LobbyClosedReason, PlayerPoints,
+        Protocol, ServerLimits, ServerMessage, WelcomeDto, BATCH, MULTI_SESSION,
+    },
     models::{
-        BiddingError, BiddingState, Card, Game, GameError, GameEvent, LobbyState, Turn, TurnError,
+        bot::{self, BotAction},
+        BiddingError, BiddingState, Card, DealState, ForfeitState, Game, GameError, GameEvent,
+        LobbyState, Turn, TurnError, MAX_PLAYER_COUNT,
     },
 };
 
-use super::repositories::{auth::AuthRepository, game::GamesRepository};
+use super::{
+    repositories::{
+        auth::AuthRepository,
+        game::{GameDto, GamesRepository},
+    },
+    stream::{self, StreamEvent, StreamFeed, StreamHands},
+    GameInfoDto, PlayerInfoDto, SpectatorInfoDto,
+};
+
+const OUTBOUND_QUEUE_SIZE: usize = 256;
+const REPLAY_BUFFER_SIZE: usize = 64;
+const BOT_DELAY: Duration = Duration::from_secs(1);
+const MIN_TURN_SECONDS: u64 = 5;
+const DISCONNECT_GRACE: Duration = Duration::from_secs(30);
+const MAX_OWNED_LOBBIES: usize = 3;
+const DEFAULT_MAX_SPECTATORS: usize = 10;
+const MAX_SPECTATORS: usize = 50;
+const MAX_CHAT_LENGTH: usize = 280;
+const CHAT_RATE_LIMIT: usize = 5;
+const CHAT_RATE_WINDOW: Duration = Duration::from_secs(10);
+const REACTION_COOLDOWN: Duration = Duration::from_secs(2);
+const MIN_STREAM_DELAY_SECONDS: u64 = 10;
+const MAX_STREAM_DELAY_SECONDS: u64 = 10 * 60;
+const DEFAULT_REAPER_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_IDLE_LOBBY_TTL: Duration = Duration::from_secs(30 * 60);
+const DEFAULT_FINISHED_GAME_TTL: Duration = Duration::from_secs(2 * 60);
+const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(15);
+const DEFAULT_PONG_TIMEOUT: Duration = Duration::from_secs(45);
+const SESSION_TAKEN_OVER: u16 = 4000;
+const HEARTBEAT_TIMEOUT: u16 = 4001;
+const UNSUPPORTED_PROTOCOL: u16 = 4002;
+const REQUEST_LOG_SIZE: usize = 64;
 
 #[derive(Clone)]
 pub struct Manager {
@@ -26,10 +77,13 @@ pub struct Manager {
 }
 
 impl Manager {
-    pub fn new(games: GamesRepository, auth: AuthRepository) -> Self {
+    pub fn new(games: GamesRepository, auth: AuthRepository, sessions: SessionConfig) -> Self {
         let inner = InnerManager {
             lobby: Mutex::new(LobbiesManager::new()),
-            connections: Mutex::new(HashMap::new()),
+            connections: Arc::new(Mutex::new(HashMap::new())),
+            connection_count: AtomicUsize::new(0),
+            sessions,
+            requests: Mutex::new(HashMap::new()),
         };
 
         Self {
@@ -39,496 +93,3635 @@ impl Manager {
         }
     }
 
-    pub async fn create_lobby(&self, user_id: String) -> String {
+    pub async fn create_lobby(
+        &self,
+        user_id: String,
+        settings: LobbySettings,
+    ) -> Result<String, LobbyError> {
+        settings.validate()?;
+
         let mut manager = self.inner.lobby.lock().await;
 
-        manager.lobbies.insert(user_id.clone(), Lobby::new());
+        manager.check_owned_lobbies(&user_id)?;
+
+        let lobby_id = generate_lobby_id();
+
+        let lobby = Lobby::new(lobby_id.clone(), user_id, settings);
+
+        manager
+            .lobbies
+            .insert(lobby_id.clone(), self.spawn_lobby(lobby));
 
-        user_id
+        Ok(lobby_id)
     }
 
-    pub async fn join_lobby(
+    pub async fn create_practice_lobby(
         &self,
-        lobby_id: String,
         user_claims: UserClaims,
-    ) -> Result<(Vec<PlayerStatus>, bool), LobbyError> {
-        let (players_status, players, should_reconnect) = {
+        bots: usize,
+    ) -> Result<JoinLobbyDto, LobbyError> {
+        // checked before anything is allocated for the bots or the lobbies lock is taken
+        if !(1..MAX_PLAYER_COUNT).contains(&bots) {
+            return Err(LobbyError::InvalidSettings("bots is out of range"));
+        }
+
+        let player_id = user_claims.id();
+
+        let (info, previous, pending) = {
             let mut manager = self.inner.lobby.lock().await;
 
-            let (players_status, info, should_reconnect) = {
-                let lobby = manager
-                    .lobbies
-                    .get_mut(&lobby_id)
-                    .ok_or(LobbyError::InvalidLobby)?;
+            manager.check_owned_lobbies(&player_id)?;
 
-                let player_id = user_claims.id();
+            let lobby_id = generate_lobby_id();
 
-                let should_reconnect = match lobby.state {
-                    LobbyState::NotStarted(_) => {
-                        let status = PlayerStatus::new(user_claims.clone());
+            let previous = manager.get_previous_lobby(&player_id, &lobby_id)?;
 
-                        lobby.players.insert(player_id, status);
+            let mut lobby = Lobby::new_practice(lobby_id.clone(), user_claims, bots)?;
 
-                        false
-                    }
-                    LobbyState::Playing(_) => {
-                        _ = lobby
-                            .players
-                            .get(&player_id)
-                            .ok_or(LobbyError::WrongLobby)?;
+            manager.reserve_join(&player_id)?;
 
-                        true
-                    }
-                };
+            for player_id in lobby.get_players_id() {
+                manager.players_lobby.insert(player_id, lobby_id.clone());
+            }
 
-                (
-                    lobby.get_players(),
-                    lobby.get_players_id(),
-                    should_reconnect,
-                )
-            };
+            let info = lobby.get_info(true);
+            let pending = lobby.start_turn();
 
-            manager.players_lobby.insert(user_claims.id(), lobby_id);
+            manager.lobbies.insert(lobby_id, self.spawn_lobby(lobby));
 
-            (players_status, info, should_reconnect)
+            (info, previous, pending)
         };
 
-        let msg = ServerMessage::PlayerJoined(user_claims);
-        self.broadcast_msg(&players, &msg).await;
+        if let Some(previous) = previous {
+            self.leave_previous_lobby(previous, player_id.clone()).await;
+        }
+
+        self.inner.lobby.lock().await.joining.remove(&player_id);
 
-        Ok((players_status, should_reconnect))
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
+        }
+
+        Ok(info)
     }
 
-    pub async fn play_turn(&self, card: Card, player_id: String) -> Result<(), LobbyError> {
-        let (players, state) = {
-            let mut manager = self.inner.lobby.lock().await;
+    pub async fn join_lobby(
+        &self,
+        lobby_id: String,
+        user_claims: UserClaims,
+        credentials: LobbyCredentials,
+    ) -> Result<JoinLobbyDto, LobbyError> {
+        let player_id = user_claims.id();
 
-            let game_id = {
-                manager
-                    .players_lobby
-                    .get(&player_id)
-                    .ok_or(LobbyError::WrongLobby)
-                    .cloned()?
-            };
+        // the membership check only holds while no other join of the same player is running
+        let (lobby, previous) = {
+            let mut manager = self.inner.lobby.lock().await;
 
-            let lobby = manager
-                .lobbies
-                .get_mut(&game_id)
-                .ok_or(LobbyError::InvalidLobby)?;
+            let previous = manager.get_previous_lobby(&player_id, &lobby_id)?;
+            let lobby = manager.get_lobby(&lobby_id)?;
 
-            if !lobby.players.contains_key(&player_id) {
-                return Err(LobbyError::WrongLobby);
-            }
+            manager.reserve_join(&player_id)?;
 
-            let game = lobby.get_game()?;
+            (lobby, previous)
+        };
 
-            let turn = Turn { player_id, card };
+        let joined = self
+            .enter_lobby(lobby, lobby_id, user_claims, credentials, previous)
+            .await;
 
-            let state = game
-                .deal(turn)
-                .map_err(|e| LobbyError::GameError(GameError::InvalidTurn(e)))?;
+        self.inner.lobby.lock().await.joining.remove(&player_id);
 
-            (lobby.get_players_id(), state)
-        };
+        joined
+    }
 
-        let msg = ServerMessage::TurnPlayed { pile: state.pile };
-        self.broadcast_msg(&players, &msg).await;
+    async fn enter_lobby(
+        &self,
+        lobby: LobbyHandle,
+        lobby_id: LobbyId,
+        user_claims: UserClaims,
+        credentials: LobbyCredentials,
+        previous: Option<LobbyId>,
+    ) -> Result<JoinLobbyDto, LobbyError> {
+        let player_id = user_claims.id();
+
+        let info = lobby
+            .call({
+                let player_id = player_id.clone();
+
+                move |lobby| {
+                    let should_reconnect = match lobby.state {
+                        LobbyState::NotStarted(_) => {
+                            if lobby.kicked.contains(&player_id) {
+                                return Err(LobbyError::Kicked);
+                            }
+
+                            if !lobby.players.contains_key(&player_id) {
+                                lobby.check_access(&player_id, &credentials)?;
+
+                                if lobby.players.len() >= lobby.settings.capacity() {
+                                    return Err(LobbyError::LobbyFull);
+                                }
+                            }
+
+                            let status = PlayerStatus::new(user_claims.clone());
+
+                            lobby.spectators.remove(&player_id);
+                            lobby.players.insert(player_id, status);
+                            lobby.touched_at = Instant::now();
+
+                            lobby.broadcast(ServerMessage::PlayerJoined(user_claims));
+
+                            false
+                        }
+                        // coming back to a running game is presence, announced once the socket is up
+                        LobbyState::Playing(_) => {
+                            _ = lobby
+                                .players
+                                .get(&player_id)
+                                .ok_or(LobbyError::WrongLobby)?;
+
+                            true
+                        }
+                    };
+
+                    Ok(lobby.get_info(should_reconnect))
+                }
+            })
+            .await?;
 
-        match state.event {
-            GameEvent::SetEnded {
-                lifes,
-                upcard,
-                decks,
-                next,
-                possible,
-            } => {
-                let msg = ServerMessage::SetEnded { lifes };
-                self.broadcast_msg(&players, &msg).await;
+        {
+            let mut manager = self.inner.lobby.lock().await;
 
-                self.init_set(decks, next, upcard, possible).await;
+            if !manager.lobbies.contains_key(&lobby_id) {
+                return Err(LobbyError::InvalidLobby);
             }
-            GameEvent::RoundEnded { rounds, next } => {
-                let msg = ServerMessage::RoundEnded(rounds);
-                self.broadcast_msg(&players, &msg).await;
 
-                let msg = ServerMessage::PlayerTurn { player_id: next };
-                self.broadcast_msg(&players, &msg).await;
-            }
-            GameEvent::TurnPlayed { next } => {
-                let msg = ServerMessage::PlayerTurn { player_id: next };
-                self.broadcast_msg(&players, &msg).await;
-            }
-            GameEvent::Ended { winner, lifes } => {
-                let msg = ServerMessage::GameEnded { winner, lifes };
-                self.broadcast_msg(&players, &msg).await;
+            if manager
+                .spectators_lobby
+                .get(&player_id)
+                .is_some_and(|l| *l == lobby_id)
+            {
+                manager.spectators_lobby.remove(&player_id);
             }
+
+            manager.players_lobby.insert(player_id.clone(), lobby_id);
         }
 
-        Ok(())
+        if let Some(previous) = previous {
+            self.leave_previous_lobby(previous, player_id).await;
+        }
+
+        Ok(info)
     }
 
-    pub async fn bid(&self, bid: usize, player_id: String) -> Result<(), LobbyError> {
-        let (players, state) = {
-            let mut manager = self.inner.lobby.lock().await;
+    pub async fn spectate(
+        &self,
+        lobby_id: String,
+        user_claims: UserClaims,
+        credentials: LobbyCredentials,
+    ) -> Result<JoinLobbyDto, LobbyError> {
+        let player_id = user_claims.id();
 
-            let lobby_id = {
-                manager
-                    .players_lobby
-                    .get(&player_id)
-                    .ok_or(LobbyError::WrongLobby)
-                    .cloned()?
-            };
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
 
-            let lobby = manager
-                .lobbies
-                .get_mut(&lobby_id)
-                .ok_or(LobbyError::InvalidLobby)?;
+        let info = lobby
+            .call({
+                let player_id = player_id.clone();
 
-            let game = lobby.get_game()?;
+                move |lobby| {
+                    if lobby.players.contains_key(&player_id) {
+                        return Err(LobbyError::AlreadyInLobby);
+                    }
 
-            let state = game
-                .bid(&player_id, bid)
-                .map_err(|e| LobbyError::GameError(GameError::InvalidBid(e)))?;
+                    if lobby.kicked.contains(&player_id) {
+                        return Err(LobbyError::Kicked);
+                    }
 
-            (lobby.get_players_id(), state)
-        };
+                    if !lobby.spectators.contains(&player_id) {
+                        lobby.check_access(&player_id, &credentials)?;
 
-        let msg = ServerMessage::PlayerBidded { player_id, bid };
-        self.broadcast_msg(&players, &msg).await;
+                        if lobby.spectators.len() >= lobby.settings.spectator_capacity() {
+                            return Err(LobbyError::SpectatorsFull);
+                        }
+                    }
 
-        let msg = match state {
-            BiddingState::Active {
-                possible_bids,
-                next,
-            } => ServerMessage::PlayerBiddingTurn {
-                player_id: next,
-                possible_bids,
-            },
-            BiddingState::Ended { next } => ServerMessage::PlayerTurn { player_id: next },
+                    lobby.spectators.insert(player_id);
+
+                    lobby.broadcast(ServerMessage::SpectatorJoined(user_claims));
+
+                    let should_reconnect = matches!(lobby.state, LobbyState::Playing(_));
+
+                    Ok(lobby.get_info(should_reconnect))
+                }
+            })
+            .await?;
+
+        let previous = {
+            let mut manager = self.inner.lobby.lock().await;
+
+            if !manager.lobbies.contains_key(&lobby_id) {
+                return Err(LobbyError::InvalidLobby);
+            }
+
+            manager
+                .spectators_lobby
+                .insert(player_id.clone(), lobby_id.clone())
+                .filter(|l| *l != lobby_id)
+                .and_then(|l| manager.lobbies.get(&l).cloned())
         };
 
-        self.broadcast_msg(&players, &msg).await;
+        if let Some(previous) = previous {
+            let left = previous
+                .call(move |lobby| {
+                    if lobby.spectators.contains(&player_id) {
+                        let msg = ServerMessage::SpectatorLeft {
+                            player_id: player_id.clone(),
+                        };
+                        lobby.broadcast(msg);
 
-        Ok(())
-    }
+                        lobby.spectators.remove(&player_id);
+                    }
 
-    pub async fn get_lobbies(&self) -> Vec<GetLobbyDto> {
-        let manager = self.inner.lobby.lock().await;
+                    Ok(())
+                })
+                .await;
 
-        manager
-            .lobbies
-            .iter()
-            .filter(|(_, lobby)| matches!(lobby.state, LobbyState::NotStarted(_)))
-            .map(|(id, lobby)| GetLobbyDto {
-                id: id.clone(),
-                player_count: lobby.players.len(),
-            })
-            .collect()
+            if let Err(e) = left {
+                tracing::error!("Error leaving previous spectated lobby | {e}");
+            }
+        }
+
+        Ok(info)
     }
 
-    pub async fn store_player_connection(
+    pub async fn stop_spectating(
         &self,
+        lobby_id: String,
         player_id: String,
-        sender: Connection,
-    ) -> Result<(), ManagerError> {
-        let mut manager = self.inner.connections.lock().await;
+    ) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
+
+        lobby
+            .call({
+                let player_id = player_id.clone();
 
-        manager.insert(player_id, sender);
+                move |lobby| {
+                    if !lobby.spectators.contains(&player_id) {
+                        return Err(LobbyError::WrongLobby);
+                    }
+
+                    let msg = ServerMessage::SpectatorLeft {
+                        player_id: player_id.clone(),
+                    };
+                    lobby.broadcast(msg);
+
+                    lobby.spectators.remove(&player_id);
+
+                    Ok(())
+                }
+            })
+            .await?;
+
+        self.inner
+            .lobby
+            .lock()
+            .await
+            .spectators_lobby
+            .remove(&player_id);
 
         Ok(())
     }
 
-    pub async fn unicast_msg(&self, player_id: &str, message: &ServerMessage) {
-        let mut manager = self.inner.connections.lock().await;
+    pub async fn watch_stream(
+        &self,
+        lobby_id: String,
+        player_id: String,
+        params: StreamParams,
+    ) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
 
-        if let Some(connection) = manager.get_mut(player_id) {
-            send_msg(message, player_id, connection).await
-        }
-    }
+        let relay = lobby
+            .call(move |lobby| {
+                let delay = lobby
+                    .settings
+                    .stream_delay
+                    .ok_or(LobbyError::StreamDisabled)?;
 
-    pub async fn send_disconnect(&self, player_id: &str, reason: ManagerError) {
-        let mut manager = self.inner.connections.lock().await;
+                if lobby.players.contains_key(&player_id) {
+                    return Err(LobbyError::AlreadyInLobby);
+                }
 
-        let connection = match manager.get_mut(player_id) {
-            Some(c) => c,
-            None => {
-                tracing::error!("{player_id} disconnected");
-                return;
-            }
-        };
+                if lobby.kicked.contains(&player_id) {
+                    return Err(LobbyError::Kicked);
+                }
 
-        let code = match reason {
-            ManagerError::PlayerDisconnected(_) => 1001,
-            ManagerError::InvalidWebsocketMessageType => 1003,
-            ManagerError::Lobby(_) => 1008,
-            ManagerError::Turn(_) | ManagerError::Bid(_) => 1008,
-            ManagerError::UnexpectedJsonMessage(_) => 1008,
-            ManagerError::UnexpectedValidMessage(_) => 1008,
-            ManagerError::Database(_) => 1011,
-            ManagerError::Unauthorized(_) => 3000,
-        };
+                lobby.check_access(&player_id, &params.credentials)?;
 
-        let send_close = connection
-            .send(Message::Close(Some(CloseFrame {
-                code,
-                reason: Cow::Owned(reason.to_string()),
-            })))
-            .await;
+                let (feed, relay) = match lobby.stream.take() {
+                    Some(feed) => (feed, None),
+                    None => {
+                        let (feed, receiver) = StreamFeed::new();
+                        let relay = (feed.id.clone(), Duration::from_secs(delay), receiver);
 
-        if let Err(e) = send_close {
-            tracing::error!("Failed to send close message: {e}")
+                        (feed, Some(relay))
+                    }
+                };
+
+                let event = StreamEvent::Viewer {
+                    player_id,
+                    omniscient: params.omniscient,
+                };
+                _ = feed.sender.send(event);
+
+                lobby.stream = Some(feed);
+
+                Ok(relay)
+            })
+            .await?;
+
+        if let Some((stream_id, delay, receiver)) = relay {
+            tokio::spawn(stream::run(self.clone(), stream_id, delay, receiver));
         }
+
+        Ok(())
     }
 
-    pub async fn player_status_change(
+    pub async fn stop_watching_stream(
         &self,
+        lobby_id: String,
         player_id: String,
-        ready: bool,
     ) -> Result<(), LobbyError> {
-        let (players, set_info) = {
-            let mut manager = self.inner.lobby.lock().await;
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
 
-            let lobby_id = {
-                manager
-                    .players_lobby
-                    .get(&player_id)
-                    .ok_or(LobbyError::WrongLobby)
-                    .cloned()?
-            };
+        lobby
+            .call(move |lobby| {
+                let feed = lobby.stream.as_ref().ok_or(LobbyError::StreamDisabled)?;
 
-            let lobby = manager
-                .lobbies
-                .get_mut(&lobby_id)
-                .ok_or(LobbyError::InvalidLobby)?;
+                _ = feed.sender.send(StreamEvent::ViewerLeft { player_id });
 
-            let players_ready = match lobby.state.borrow_mut() {
-                LobbyState::NotStarted(p) => p,
-                LobbyState::Playing(_) => return Err(LobbyError::GameAlreadyStarted),
-            };
+                Ok(())
+            })
+            .await
+    }
 
-            if ready {
-                players_ready.insert(player_id.clone())
-            } else {
-                players_ready.remove(&player_id)
-            };
+    pub async fn chat(&self, player_id: String, text: String) -> Result<(), LobbyError> {
+        let text = text.trim();
 
-            let should_start = players_ready.len() == lobby.players.len();
+        if text.is_empty() {
+            return Err(LobbyError::InvalidChatMessage("message is empty"));
+        }
 
-            let set_info = if should_start {
-                let game = Game::new_default(lobby.get_players_id())?;
+        if text.chars().count() > MAX_CHAT_LENGTH {
+            return Err(LobbyError::InvalidChatMessage("message is too long"));
+        }
 
-                let (decks, upcard) = game.get_decks();
+        let lobby = self.inner.lobby.lock().await.get_member_lobby(&player_id)?;
 
-                let first = game.get_bidding_player();
+        let msg = ServerMessage::Chat {
+            player_id: player_id.clone(),
+            text: text.to_string(),
+            time: Utc::now(),
+        };
 
-                let possible = game.get_possible_bids();
+        lobby
+            .call(move |lobby| {
+                lobby.check_chat_rate(&player_id)?;
 
-                lobby.state = LobbyState::Playing(game);
+                lobby.broadcast(msg);
 
-                Some((decks, first, upcard, possible))
-            } else {
-                None
-            };
+                Ok(())
+            })
+            .await
+    }
 
-            (lobby.get_players_id(), set_info)
-        };
+    pub async fn react(&self, player_id: String, emote: Emote) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_member_lobby(&player_id)?;
+
+        lobby
+            .call(move |lobby| {
+                lobby.check_reaction_cooldown(&player_id)?;
+
+                lobby.broadcast(ServerMessage::Reaction { player_id, emote });
+
+                Ok(())
+            })
+            .await
+    }
 
-        let msg = ServerMessage::PlayerStatusChange { player_id, ready };
-        self.broadcast_msg(&players, &msg).await;
+    pub async fn play_turn(&self, card: Card, player_id: String) -> Result<(), LobbyError> {
+        let (_, lobby) = self.inner.lobby.lock().await.get_player_lobby(&player_id)?;
+
+        let pending = lobby
+            .call(move |lobby| lobby.play_card(player_id, card))
+            .await?;
+
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
+        }
+
+        Ok(())
+    }
+
+    pub async fn bid(&self, bid: usize, player_id: String) -> Result<(), LobbyError> {
+        let (_, lobby) = self.inner.lobby.lock().await.get_player_lobby(&player_id)?;
+
+        let pending = lobby
+            .call(move |lobby| lobby.place_bid(player_id, bid))
+            .await?;
 
-        if let Some((decks, first, upcard, possible_bids)) = set_info {
-            self.init_set(decks, first, upcard, possible_bids).await;
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
         }
 
         Ok(())
     }
 
-    async fn init_set(
+    pub async fn leave_lobby(&self, lobby_id: String, player_id: String) -> Result<(), LobbyError> {
+        self.remove_waiting_player(lobby_id, player_id, None).await
+    }
+
+    pub async fn kick_player(
+        &self,
+        lobby_id: String,
+        host_id: String,
+        player_id: String,
+    ) -> Result<(), LobbyError> {
+        if host_id == player_id {
+            return self.leave_lobby(lobby_id, player_id).await;
+        }
+
+        self.remove_waiting_player(lobby_id, player_id, Some(host_id))
+            .await
+    }
+
+    async fn remove_waiting_player(
         &self,
-        decks: IndexMap<String, Vec<Card>>,
-        next: String,
-        upcard: Card,
-        possible_bids: Vec<usize>,
-    ) {
-        let players: Vec<_> = decks.keys().cloned().collect();
+        lobby_id: String,
+        player_id: String,
+        kicked_by: Option<String>,
+    ) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
 
-        let msg = ServerMessage::SetStart { upcard };
-        self.broadcast_msg(&players, &msg).await;
+        let (pending, has_humans) = lobby
+            .call({
+                let player_id = player_id.clone();
 
-        for (p, deck) in decks {
-            let msg = ServerMessage::PlayerDeck(deck);
+                move |lobby| {
+                    if let Some(host_id) = &kicked_by {
+                        lobby.check_host(host_id)?;
+                    }
+
+                    if !lobby.players.contains_key(&player_id) {
+                        return Err(match kicked_by {
+                            Some(_) => LobbyError::PlayerNotFound,
+                            None => LobbyError::WrongLobby,
+                        });
+                    }
+
+                    match lobby.state.borrow_mut() {
+                        LobbyState::NotStarted(ready) => ready.remove(&player_id),
+                        LobbyState::Playing(_) => return Err(LobbyError::GameAlreadyStarted),
+                    };
+
+                    let msg = match kicked_by {
+                        Some(_) => ServerMessage::PlayerKicked {
+                            player_id: player_id.clone(),
+                        },
+                        None => ServerMessage::PlayerLeft {
+                            player_id: player_id.clone(),
+                        },
+                    };
+                    lobby.broadcast(msg);
+
+                    lobby.players.shift_remove(&player_id);
+
+                    if kicked_by.is_some() {
+                        lobby.kicked.insert(player_id.clone());
+                    }
+
+                    lobby.touched_at = Instant::now();
+
+                    if let Some(host_id) = lobby.reassign_host(&player_id) {
+                        lobby.broadcast(ServerMessage::HostChanged { player_id: host_id });
+                    }
 
-            self.unicast_msg(&p, &msg).await;
+                    let set_info = match lobby.players.len() > 1 {
+                        true => lobby.try_start()?,
+                        false => None,
+                    };
+
+                    let pending = match set_info {
+                        Some(set_info) => {
+                            lobby.init_set(set_info);
+                            lobby.start_turn()
+                        }
+                        None => None,
+                    };
+
+                    Ok((pending, lobby.has_humans()))
+                }
+            })
+            .await?;
+
+        self.remove_player(&lobby_id, &player_id, has_humans).await;
+
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
         }
 
-        let msg = ServerMessage::PlayerBiddingTurn {
-            player_id: next,
-            possible_bids,
+        Ok(())
+    }
+
+    pub async fn leave(&self, player_id: String) -> Result<(), LobbyError> {
+        let lobby_id = {
+            let manager = self.inner.lobby.lock().await;
+
+            manager
+                .players_lobby
+                .get(&player_id)
+                .ok_or(LobbyError::WrongLobby)
+                .cloned()?
         };
 
-        self.broadcast_msg(&players, &msg).await;
+        self.leave_from(lobby_id, player_id).await
     }
 
-    async fn broadcast_msg(&self, players: &[String], msg: &ServerMessage) {
-        for p in players {
-            let mut connections = self.inner.connections.lock().await;
+    async fn leave_previous_lobby(&self, lobby_id: LobbyId, player_id: PlayerId) {
+        tracing::info!("{player_id} joined another lobby, leaving {lobby_id}");
 
-            if let Some(c) = connections.get_mut(p) {
-                send_msg(msg, p, c).await;
-            }
+        if let Err(e) = self.leave_from(lobby_id, player_id).await {
+            tracing::error!("Error leaving previous lobby | {e}");
         }
     }
 
-    pub async fn reconnect(&self, player_id: String) -> Result<(), LobbyError> {
-        let info = {
-            let mut manager = self.inner.lobby.lock().await;
+    async fn leave_from(&self, lobby_id: LobbyId, player_id: PlayerId) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
 
-            let lobby_id = {
-                manager
-                    .players_lobby
-                    .get(&player_id)
-                    .ok_or(LobbyError::WrongLobby)
-                    .cloned()?
-            };
+        let forfeit = lobby
+            .call({
+                let player_id = player_id.clone();
 
-            let lobby = manager
-                .lobbies
-                .get_mut(&lobby_id)
-                .ok_or(LobbyError::InvalidLobby)?;
+                move |lobby| {
+                    if let LobbyState::NotStarted(_) = lobby.state {
+                        return Ok(None);
+                    }
+
+                    if !lobby.players.contains_key(&player_id) {
+                        return Err(LobbyError::WrongLobby);
+                    }
+
+                    let msg = ServerMessage::PlayerLeft {
+                        player_id: player_id.clone(),
+                    };
+
+                    // the result of a finished game stays as it is
+                    if lobby.is_finished() {
+                        lobby.broadcast(msg);
+
+                        let pending = lobby.leave_finished(&player_id)?;
+
+                        return Ok(Some((pending, lobby.has_humans())));
+                    }
+
+                    let was_current = lobby.is_current_player(&player_id);
+
+                    let state = lobby
+                        .get_game()?
+                        .forfeit(&player_id)
+                        .map_err(|e| LobbyError::GameError(GameError::InvalidTurn(e)))?;
+
+                    lobby.broadcast(msg);
+
+                    lobby.players.shift_remove(&player_id);
+                    lobby.bots.remove(&player_id);
+                    lobby.away.remove(&player_id);
+
+                    if let Some(host_id) = lobby.reassign_host(&player_id) {
+                        lobby.broadcast(ServerMessage::HostChanged { player_id: host_id });
+                    }
+
+                    let advanced = match &state {
+                        ForfeitState::Bidding(_) => false,
+                        ForfeitState::Dealing(s) => {
+                            !matches!(s.event, GameEvent::TurnPlayed { .. })
+                        }
+                        ForfeitState::Ended { .. } => true,
+                    };
+
+                    match state {
+                        ForfeitState::Bidding(state) => lobby.send_bidding_state(state),
+                        ForfeitState::Dealing(state) => lobby.send_deal_state(state),
+                        ForfeitState::Ended { winner, lifes } => lobby.end_game(winner, lifes),
+                    }
+
+                    let pending = match was_current || advanced {
+                        true => lobby.start_turn(),
+                        false => None,
+                    };
+
+                    Ok(Some((pending, lobby.has_humans())))
+                }
+            })
+            .await?;
 
-            lobby.get_game()?.get_game_info(&player_id)
+        let Some((pending, has_humans)) = forfeit else {
+            return self.leave_lobby(lobby_id, player_id).await;
         };
 
-        let msg = ServerMessage::Reconnect(info);
+        self.remove_player(&lobby_id, &player_id, has_humans).await;
+
+        tracing::info!("{player_id} left started lobby {lobby_id}");
 
-        self.unicast_msg(&player_id, &msg).await;
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
+        }
 
         Ok(())
     }
 
-    pub async fn send_error(&self, id: &str, error: ManagerError) {
-        let msg = ServerMessage::Error {
-            msg: error.to_string(),
-        };
+    pub async fn transfer_host(
+        &self,
+        lobby_id: String,
+        host_id: String,
+        player_id: String,
+    ) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
 
-        self.unicast_msg(id, &msg).await;
-    }
-}
+        lobby
+            .call(move |lobby| {
+                lobby.check_host(&host_id)?;
 
-async fn send_msg(msg: &ServerMessage, player: &str, connection: &mut Connection) {
-    let msg = serde_json::to_string(msg).expect("Should be valid json");
+                match lobby.players.get(&player_id) {
+                    Some(p) if !matches!(p.player, UserClaims::Bot(_)) => {}
+                    _ => return Err(LobbyError::PlayerNotFound),
+                }
 
-    tracing::info!("Sending to {player}: {msg}");
+                lobby.host = player_id.clone();
+                lobby.touched_at = Instant::now();
 
-    let send = connection
-        .send(Message::Text(msg))
-        .await
-        .map_err(|e| ManagerError::PlayerDisconnected(e.to_string()));
+                lobby.broadcast(ServerMessage::HostChanged { player_id });
 
-    if let Err(e) = send {
-        tracing::error!("Error sending msg to: {player} | {e}");
+                Ok(())
+            })
+            .await
+    }
+
+    pub async fn update_settings(
+        &self,
+        lobby_id: String,
+        host_id: String,
+        settings: LobbySettings,
+    ) -> Result<(), LobbyError> {
+        settings.validate()?;
+
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
+
+        lobby
+            .call(move |lobby| {
+                lobby.check_host(&host_id)?;
+
+                if let LobbyState::Playing(_) = lobby.state {
+                    return Err(LobbyError::GameAlreadyStarted);
+                }
+
+                if lobby.players.len() > settings.capacity() {
+                    return Err(LobbyError::InvalidSettings(
+                        "lobby already has more players than max_players",
+                    ));
+                }
+
+                let mut settings = settings;
+                settings.keep_password(&lobby.settings);
+
+                lobby.settings = settings.clone();
+                lobby.touched_at = Instant::now();
+
+                lobby.broadcast(ServerMessage::SettingsChanged(settings));
+
+                Ok(())
+            })
+            .await
+    }
+
+    pub async fn start_lobby(&self, lobby_id: String, host_id: String) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
+
+        let pending = lobby
+            .call({
+                let host_id = host_id.clone();
+
+                move |lobby| {
+                    lobby.check_host(&host_id)?;
+
+                    let set_info = lobby.start()?;
+
+                    lobby.init_set(set_info);
+
+                    Ok(lobby.start_turn())
+                }
+            })
+            .await?;
+
+        tracing::info!("{host_id} started lobby {lobby_id}");
+
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
+        }
+
+        Ok(())
+    }
+
+    pub async fn create_invite(
+        &self,
+        lobby_id: String,
+        host_id: String,
+    ) -> Result<InviteDto, LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
+
+        let invite_id = lobby
+            .call(move |lobby| {
+                lobby.check_host(&host_id)?;
+
+                let invite_id = nanoid::nanoid!(10);
+
+                lobby.invites.insert(invite_id.clone());
+
+                Ok(invite_id)
+            })
+            .await?;
+
+        let token = auth::create_invite_token(lobby_id, invite_id.clone());
+
+        Ok(InviteDto { invite_id, token })
+    }
+
+    pub async fn revoke_invite(
+        &self,
+        lobby_id: String,
+        host_id: String,
+        invite_id: String,
+    ) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
+
+        lobby
+            .call(move |lobby| {
+                lobby.check_host(&host_id)?;
+
+                match lobby.invites.remove(&invite_id) {
+                    true => Ok(()),
+                    false => Err(LobbyError::InvalidInvite),
+                }
+            })
+            .await
+    }
+
+    pub async fn rematch_vote(&self, player_id: String, accept: bool) -> Result<(), LobbyError> {
+        let (lobby_id, lobby) = self.inner.lobby.lock().await.get_player_lobby(&player_id)?;
+
+        let (pending, has_humans) = lobby
+            .call({
+                let player_id = player_id.clone();
+
+                move |lobby| {
+                    if !lobby.is_finished() {
+                        return Err(LobbyError::GameNotEnded);
+                    }
+
+                    let msg = ServerMessage::RematchVote {
+                        player_id: player_id.clone(),
+                        accept,
+                    };
+                    lobby.broadcast(msg);
+
+                    let pending = match accept {
+                        true => {
+                            lobby.rematch.insert(player_id);
+                            lobby.start_rematch()?
+                        }
+                        false => lobby.leave_finished(&player_id)?,
+                    };
+
+                    Ok((pending, lobby.has_humans()))
+                }
+            })
+            .await?;
+
+        if !accept {
+            self.remove_player(&lobby_id, &player_id, has_humans).await;
+        }
+
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
+        }
+
+        Ok(())
+    }
+
+    pub fn start_reaper(&self, config: ReaperConfig) {
+        let manager = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.interval);
+
+            loop {
+                interval.tick().await;
+                manager.reap(config).await;
+            }
+        });
+    }
+
+    async fn reap(&self, config: ReaperConfig) {
+        let lobbies: Vec<_> = {
+            let manager = self.inner.lobby.lock().await;
+
+            manager
+                .lobbies
+                .iter()
+                .map(|(id, lobby)| (id.clone(), lobby.clone()))
+                .collect()
+        };
+
+        let now = Instant::now();
+
+        for (lobby_id, lobby) in lobbies {
+            let expired = lobby
+                .call(move |lobby| {
+                    let Some(reason) = lobby.expired(now, &config) else {
+                        return Ok(None);
+                    };
+
+                    let msg = ServerMessage::LobbyClosed {
+                        lobby_id: lobby.id.clone(),
+                        reason,
+                    };
+                    lobby.broadcast(msg);
+
+                    Ok(Some(reason))
+                })
+                .await;
+
+            let expired = match expired {
+                Ok(expired) => expired,
+                Err(e) => {
+                    if lobby.is_closed() {
+                        tracing::error!("Dropping dead lobby {lobby_id} | {e}");
+                        self.remove_lobby(&lobby_id).await;
+                    }
+
+                    continue;
+                }
+            };
+
+            let Some(reason) = expired else {
+                continue;
+            };
+
+            self.remove_lobby(&lobby_id).await;
+
+            tracing::info!("Reaping lobby {lobby_id} | {reason:?}");
+        }
+    }
+
+    async fn remove_player(&self, lobby_id: &str, player_id: &str, has_humans: bool) {
+        let removed = self
+            .inner
+            .lobby
+            .lock()
+            .await
+            .remove_player(lobby_id, player_id, has_humans);
+
+        self.forget_requests(removed).await;
+    }
+
+    async fn remove_lobby(&self, lobby_id: &str) {
+        let removed = self.inner.lobby.lock().await.remove_lobby(lobby_id);
+
+        self.forget_requests(removed).await;
+    }
+
+    /// Offline players without a lobby have nothing left to retry
+    async fn forget_requests(&self, players: Vec<PlayerId>) {
+        let connections = self.inner.connections.lock().await;
+        let mut requests = self.inner.requests.lock().await;
+
+        for player_id in players {
+            if !connections.contains_key(&player_id) {
+                requests.remove(&player_id);
+            }
+        }
+    }
+
+    pub async fn get_lobbies(&self) -> Vec<GetLobbyDto> {
+        let manager = self.inner.lobby.lock().await;
+
+        manager
+            .lobbies
+            .iter()
+            .map(|(id, lobby)| (id, lobby.get_listing()))
+            .filter(|(_, listing)| !listing.practice)
+            .filter(|(_, listing)| listing.visibility == Visibility::Public)
+            .filter(|(_, listing)| !listing.started)
+            .map(|(id, listing)| GetLobbyDto {
+                id: id.clone(),
+                host: listing.host,
+                creator: listing.creator,
+                created_at: listing.created_at,
+                player_count: listing.player_count,
+            })
+            .collect()
+    }
+
+    fn spawn_lobby(&self, lobby: Lobby) -> LobbyHandle {
+        LobbyHandle::spawn(
+            lobby,
+            self.inner.connections.clone(),
+            self.games_repo.clone(),
+        )
+    }
+
+    pub async fn store_player_connection(
+        &self,
+        player_id: String,
+        sink: Connection,
+        protocol: Protocol,
+    ) -> Result<(ConnectionId, JoinHandle<()>), ManagerError> {
+        let (sender, receiver) = mpsc::channel(OUTBOUND_QUEUE_SIZE);
+
+        let writer = tokio::spawn(write_messages(sink, receiver));
+
+        let mut manager = self.inner.connections.lock().await;
+
+        let id = self.inner.connection_count.fetch_add(1, Ordering::Relaxed);
+
+        let connection = PlayerConnection {
+            id,
+            sender,
+            writer: writer.abort_handle(),
+            protocol,
+        };
+
+        let connections = manager.entry(player_id.clone()).or_default();
+
+        take_over_sessions(
+            connections,
+            &player_id,
+            &connection.protocol,
+            self.inner.sessions.take_over,
+        );
+
+        connections.push(connection);
+
+        Ok((id, writer))
+    }
+
+    pub async fn player_connected(&self, player_id: String) {
+        let lobby = match self.inner.lobby.lock().await.get_player_lobby(&player_id) {
+            Ok((_, lobby)) => lobby,
+            Err(_) => return,
+        };
+
+        let result = lobby
+            .call(move |lobby| {
+                lobby.mark_back(player_id);
+
+                Ok(())
+            })
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!("Error while marking player as connected | {e}");
+        }
+    }
+
+    pub async fn player_disconnected(&self, player_id: String, connection_id: ConnectionId) {
+        {
+            let mut manager = self.inner.connections.lock().await;
+
+            let Some(connections) = manager.get_mut(&player_id) else {
+                return;
+            };
+
+            let Some(index) = connections.iter().position(|c| c.id == connection_id) else {
+                return;
+            };
+
+            connections.remove(index);
+
+            // the player is still online through another tab or device
+            if !connections.is_empty() {
+                return;
+            }
+
+            manager.remove(&player_id);
+        }
+
+        let (lobby_id, lobby) = match self.inner.lobby.lock().await.get_player_lobby(&player_id) {
+            Ok(lobby) => lobby,
+            Err(_) => {
+                // outside a game there is nothing worth retrying after a reconnect
+                self.inner.requests.lock().await.remove(&player_id);
+                self.spectator_disconnected(player_id).await;
+                return;
+            }
+        };
+
+        let away = lobby
+            .call({
+                let player_id = player_id.clone();
+
+                move |lobby| Ok(lobby.mark_away(player_id))
+            })
+            .await;
+
+        // armed in waiting lobbies too, the game may start before the player is back
+        if let Ok(since) = away {
+            let manager = self.clone();
+
+            tokio::spawn(async move {
+                tokio::time::sleep(DISCONNECT_GRACE).await;
+
+                if let Err(e) = manager.bot_takeover(lobby_id, player_id, since).await {
+                    tracing::error!("Error during bot takeover | {e}");
+                }
+            });
+        }
+    }
+
+    async fn spectator_disconnected(&self, player_id: PlayerId) {
+        let lobby = {
+            let mut manager = self.inner.lobby.lock().await;
+
+            let Some(lobby_id) = manager.spectators_lobby.remove(&player_id) else {
+                return;
+            };
+
+            match manager.get_lobby(&lobby_id) {
+                Ok(lobby) => lobby,
+                Err(_) => return,
+            }
+        };
+
+        let result = lobby
+            .call(move |lobby| {
+                if lobby.spectators.contains(&player_id) {
+                    let msg = ServerMessage::SpectatorLeft {
+                        player_id: player_id.clone(),
+                    };
+                    lobby.broadcast(msg);
+
+                    lobby.spectators.remove(&player_id);
+                }
+
+                Ok(())
+            })
+            .await;
+
+        if let Err(e) = result {
+            tracing::error!("Error while removing disconnected spectator | {e}");
+        }
+    }
+
+    async fn bot_takeover(
+        &self,
+        lobby_id: LobbyId,
+        player_id: PlayerId,
+        since: Instant,
+    ) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&lobby_id)?;
+
+        let pending = lobby
+            .call(move |lobby| {
+                if lobby.away.get(&player_id) != Some(&since) {
+                    return Ok(None);
+                }
+
+                lobby.bots.insert(player_id.clone());
+
+                let is_current = lobby.is_current_player(&player_id);
+
+                lobby.broadcast(ServerMessage::BotTakeover { player_id });
+
+                Ok(match is_current {
+                    true => lobby.start_turn(),
+                    false => None,
+                })
+            })
+            .await?;
+
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
+        }
+
+        Ok(())
+    }
+
+    pub async fn unicast_msg(&self, player_id: &str, message: &ServerMessage) {
+        let manager = self.inner.connections.lock().await;
+
+        if let Some(connections) = manager.get(player_id) {
+            send_msg(message, player_id, connections)
+        }
+    }
+
+    pub async fn send_disconnect(&self, player_id: &str, reason: ManagerError) {
+        let manager = self.inner.connections.lock().await;
+
+        let connections = match manager.get(player_id) {
+            Some(c) => c,
+            None => {
+                tracing::error!("{player_id} disconnected");
+                return;
+            }
+        };
+
+        let code = reason.close_code();
+
+        for connection in connections {
+            connection.enqueue(player_id, close_message(code, reason.to_string()));
+        }
+    }
+
+    pub async fn player_status_change(
+        &self,
+        player_id: String,
+        ready: bool,
+    ) -> Result<(), LobbyError> {
+        let (_, lobby) = self.inner.lobby.lock().await.get_player_lobby(&player_id)?;
+
+        let pending = lobby
+            .call(move |lobby| {
+                let players_ready = match lobby.state.borrow_mut() {
+                    LobbyState::NotStarted(p) => p,
+                    LobbyState::Playing(_) => return Err(LobbyError::GameAlreadyStarted),
+                };
+
+                if ready {
+                    players_ready.insert(player_id.clone())
+                } else {
+                    players_ready.remove(&player_id)
+                };
+
+                lobby.touched_at = Instant::now();
+
+                let set_info = lobby.try_start()?;
+
+                lobby.broadcast(ServerMessage::PlayerStatusChange { player_id, ready });
+
+                if let Some(set_info) = set_info {
+                    lobby.init_set(set_info);
+                }
+
+                Ok(lobby.start_turn())
+            })
+            .await?;
+
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
+        }
+
+        Ok(())
+    }
+
+    fn schedule_turn(&self, pending: PendingTurn) {
+        let manager = self.clone();
+
+        tokio::spawn(async move {
+            let delay = match pending.auto_play {
+                AutoPlay::Bot => BOT_DELAY,
+                AutoPlay::Timeout(timeout) => timeout,
+            };
+
+            tokio::time::sleep(delay).await;
+
+            if let Err(e) = manager.auto_play(pending).await {
+                tracing::error!("Error while auto playing turn | {e}");
+            }
+        });
+    }
+
+    async fn auto_play(&self, pending: PendingTurn) -> Result<(), LobbyError> {
+        let lobby = self.inner.lobby.lock().await.get_lobby(&pending.lobby_id)?;
+
+        let info = lobby
+            .call({
+                let player_id = pending.player_id.clone();
+                let turn = pending.turn;
+
+                move |lobby| {
+                    if lobby.turn != turn {
+                        return Ok(None);
+                    }
+
+                    Ok(Some(lobby.get_game()?.get_game_info(&player_id)))
+                }
+            })
+            .await?;
+
+        let Some(info) = info else {
+            return Ok(());
+        };
+
+        let action = match pending.auto_play {
+            // the simulations take a while, keep them off the async workers and the lobby
+            AutoPlay::Bot => {
+                let player_id = pending.player_id.clone();
+
+                tokio::task::spawn_blocking(move || bot::choose_action(&info, &player_id))
+                    .await
+                    .map_err(|_| LobbyError::SimulationFailed)?
+            }
+            AutoPlay::Timeout(_) => bot::fallback_action(&info),
+        };
+
+        let Some(action) = action else {
+            return Ok(());
+        };
+
+        let next = lobby
+            .call(move |lobby| {
+                // the player may have moved meanwhile and already be on a new turn
+                if lobby.turn != pending.turn {
+                    return Ok(None);
+                }
+
+                match action {
+                    BotAction::Bid(bid) => lobby.place_bid(pending.player_id, bid),
+                    BotAction::Play(card) => lobby.play_card(pending.player_id, card),
+                }
+            })
+            .await?;
+
+        if let Some(next) = next {
+            self.schedule_turn(next);
+        }
+
+        Ok(())
+    }
+
+    pub async fn hint(&self, player_id: String) -> Result<(), LobbyError> {
+        let (_, lobby) = self.inner.lobby.lock().await.get_player_lobby(&player_id)?;
+
+        let (info, turn) = lobby
+            .call({
+                let player_id = player_id.clone();
+
+                move |lobby| {
+                    if !lobby.settings.hints {
+                        return Err(LobbyError::HintsDisabled);
+                    }
+
+                    let turn = lobby.turn;
+                    let game = lobby.get_game()?;
+
+                    if game.get_current_player().as_ref() != Some(&player_id) {
+                        return Err(LobbyError::NotYourTurn);
+                    }
+
+                    Ok((game.get_game_info(&player_id), turn))
+                }
+            })
+            .await?;
+
+        // the simulations take a while, keep them off the async workers and the lobby
+        let hint = tokio::task::spawn_blocking({
+            let player_id = player_id.clone();
+
+            move || bot::suggest(&info, &player_id)
+        })
+        .await
+        .map_err(|_| LobbyError::SimulationFailed)?
+        .ok_or(LobbyError::NotYourTurn)?;
+
+        lobby
+            .call(move |lobby| {
+                // the hint is stale once the player has moved on
+                if lobby.turn != turn {
+                    return Err(LobbyError::NotYourTurn);
+                }
+
+                lobby.unicast(&player_id, ServerMessage::Hint(hint));
+
+                Ok(())
+            })
+            .await
+    }
+
+    pub async fn reconnect(
+        &self,
+        player_id: String,
+        last_seen: Option<(LobbyId, u64)>,
+    ) -> Result<(), LobbyError> {
+        let (_, lobby) = {
+            let manager = self.inner.lobby.lock().await;
+
+            match manager.players_lobby.contains_key(&player_id) {
+                true => manager.get_player_lobby(&player_id)?,
+                false => {
+                    drop(manager);
+                    return self.reconnect_spectator(player_id, last_seen).await;
+                }
+            }
+        };
+
+        let pending = lobby
+            .call(move |lobby| {
+                if !lobby.try_replay(&player_id, last_seen) {
+                    let info = lobby.get_game_info(&player_id)?;
+
+                    lobby.unicast(&player_id, ServerMessage::Reconnect(info));
+                }
+
+                if lobby.bots.remove(&player_id) && lobby.is_current_player(&player_id) {
+                    return Ok(lobby.start_turn());
+                }
+
+                if let Some(msg) = lobby.get_turn_timer() {
+                    lobby.unicast(&player_id, msg);
+                }
+
+                Ok(None)
+            })
+            .await?;
+
+        if let Some(pending) = pending {
+            self.schedule_turn(pending);
+        }
+
+        Ok(())
+    }
+
+    async fn reconnect_spectator(
+        &self,
+        player_id: String,
+        last_seen: Option<(LobbyId, u64)>,
+    ) -> Result<(), LobbyError> {
+        let lobby = {
+            let manager = self.inner.lobby.lock().await;
+
+            let lobby_id = manager
+                .spectators_lobby
+                .get(&player_id)
+                .ok_or(LobbyError::WrongLobby)?;
+
+            manager.get_lobby(lobby_id)?
+        };
+
+        lobby
+            .call(move |lobby| {
+                if !lobby.try_replay(&player_id, last_seen) {
+                    let info = lobby.get_spectator_info()?;
+
+                    lobby.unicast(&player_id, ServerMessage::SpectatorReconnect(info));
+                }
+
+                if let Some(msg) = lobby.get_turn_timer() {
+                    lobby.unicast(&player_id, msg);
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    pub fn session_config(&self) -> SessionConfig {
+        self.inner.sessions
+    }
+
+    pub fn welcome(&self, protocol: &Protocol) -> WelcomeDto {
+        let limits = ServerLimits {
+            max_players: MAX_PLAYER_COUNT,
+            max_owned_lobbies: MAX_OWNED_LOBBIES,
+            max_spectators: MAX_SPECTATORS,
+            min_turn_seconds: MIN_TURN_SECONDS,
+            min_stream_delay_seconds: MIN_STREAM_DELAY_SECONDS,
+            max_stream_delay_seconds: MAX_STREAM_DELAY_SECONDS,
+            max_chat_length: MAX_CHAT_LENGTH,
+            chat_rate_limit: CHAT_RATE_LIMIT,
+            chat_rate_window_seconds: CHAT_RATE_WINDOW.as_secs(),
+            reaction_cooldown_seconds: REACTION_COOLDOWN.as_secs(),
+            replay_buffer_size: REPLAY_BUFFER_SIZE,
+            ping_interval_seconds: self.inner.sessions.ping_interval.as_secs(),
+            pong_timeout_seconds: self.inner.sessions.pong_timeout.as_secs(),
+        };
+
+        WelcomeDto {
+            protocol_version: protocol.version,
+            capabilities: protocol.capabilities.clone(),
+            features: Feature::iter().collect(),
+            limits,
+        }
+    }
+
+    pub async fn ping(&self, player_id: &str, connection_id: ConnectionId) {
+        self.send_to_connection(player_id, connection_id, Message::Ping(Vec::new()))
+            .await;
+    }
+
+    pub async fn close_stale(&self, player_id: &str, connection_id: ConnectionId) {
+        let close = close_message(HEARTBEAT_TIMEOUT, "Heartbeat timeout");
+
+        self.send_to_connection(player_id, connection_id, close)
+            .await;
+    }
+
+    async fn send_to_connection(&self, player_id: &str, connection_id: ConnectionId, msg: Message) {
+        let manager = self.inner.connections.lock().await;
+
+        let connection = manager
+            .get(player_id)
+            .and_then(|connections| connections.iter().find(|c| c.id == connection_id));
+
+        if let Some(connection) = connection {
+            connection.enqueue(player_id, msg);
+        }
+    }
+
+    pub async fn send_error(&self, id: &str, connection_id: ConnectionId, error: ManagerError) {
+        let msg = ServerMessage::Error {
+            msg: error.to_string(),
+        };
+
+        self.reply(id, connection_id, &msg).await;
+    }
+
+    pub async fn reply(&self, player_id: &str, connection_id: ConnectionId, msg: &ServerMessage) {
+        let msg = serde_json::to_string(msg).expect("Should be valid json");
+
+        tracing::info!("Sending to {player_id}: {msg}");
+
+        self.send_to_connection(player_id, connection_id, Message::Text(msg))
+            .await;
+    }
+
+    pub async fn start_request(
+        &self,
+        player_id: &str,
+        request_id: &str,
+        connection_id: ConnectionId,
+    ) -> RequestState {
+        let mut requests = self.inner.requests.lock().await;
+
+        requests
+            .entry(player_id.to_string())
+            .or_default()
+            .start(request_id, connection_id)
+    }
+
+    /// Stores the reply and returns the connections that repeated the id while it was running
+    pub async fn finish_request(
+        &self,
+        player_id: &str,
+        request_id: &str,
+        reply: ServerMessage,
+    ) -> Vec<ConnectionId> {
+        let mut requests = self.inner.requests.lock().await;
+
+        requests
+            .get_mut(player_id)
+            .map(|log| log.finish(request_id, reply))
+            .unwrap_or_default()
+    }
+}
+
+async fn archive_game(repo: GamesRepository, game: GameDto) {
+    if let Err(e) = repo.insert_game(&game).await {
+        tracing::error!("Error while archiving game | {e}");
+    }
+}
+
+fn send_msg(msg: &ServerMessage, player: &str, connections: &[PlayerConnection]) {
+    let msg = to_text(msg, player);
+
+    for connection in connections {
+        connection.enqueue(player, msg.clone());
+    }
+}
+
+/// Connections that negotiated `batch` get the whole envelope, the rest get its events one by one
+fn send_batch(batch: EventBatch, player: &str, connections: &[PlayerConnection]) {
+    let (batched, plain): (Vec<_>, Vec<_>) =
+        connections.iter().partition(|c| c.protocol.supports(BATCH));
+
+    if !plain.is_empty() {
+        for msg in &batch.events {
+            let msg = to_text(msg, player);
+
+            for connection in &plain {
+                connection.enqueue(player, msg.clone());
+            }
+        }
+    }
+
+    if !batched.is_empty() {
+        let msg = to_text(&ServerMessage::Batch(batch), player);
+
+        for connection in batched {
+            connection.enqueue(player, msg.clone());
+        }
+    }
+}
+
+/// Closes the connections a new one with `incoming` replaces, clients that didn't negotiate
+/// `multi_session` expect a single live connection
+fn take_over_sessions(
+    connections: &mut Vec<PlayerConnection>,
+    player_id: &str,
+    incoming: &Protocol,
+    take_over: bool,
+) {
+    let take_over = take_over || !incoming.supports(MULTI_SESSION);
+
+    let (taken_over, kept): (Vec<_>, Vec<_>) = std::mem::take(connections)
+        .into_iter()
+        .partition(|old| take_over || !old.protocol.supports(MULTI_SESSION));
+
+    *connections = kept;
+
+    // dropping the old senders lets their writers flush the close frame and finish
+    for old in taken_over {
+        old.enqueue(
+            player_id,
+            close_message(SESSION_TAKEN_OVER, "Session taken over"),
+        );
+    }
+}
+
+fn to_text(msg: &ServerMessage, player: &str) -> Message {
+    let msg = serde_json::to_string(msg).expect("Should be valid json");
+
+    tracing::info!("Sending to {player}: {msg}");
+
+    Message::Text(msg)
+}
+
+fn close_message(code: u16, reason: impl Into<Cow<'static, str>>) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+async fn write_messages(mut sink: Connection, mut receiver: mpsc::Receiver<Message>) {
+    while let Some(msg) = receiver.recv().await {
+        if let Err(e) = sink.send(msg).await {
+            tracing::error!("Error writing to websocket | {e}");
+            break;
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, strum_macros::IntoStaticStr)]
+pub enum ManagerError {
+    #[error("Player disconnected | {0}")]
+    PlayerDisconnected(String),
+    #[error("Error processing turn: {0:?}")]
+    Turn(#[from] TurnError),
+    #[error("Error processing bid: {0:?}")]
+    Bid(#[from] BiddingError),
+    #[error("Invalid websocket message type")]
+    InvalidWebsocketMessageType,
+    #[error("Unexpected valid json message: {0}")]
+    UnexpectedJsonMessage(#[from] serde_json::error::Error),
+    #[error("Unexpected message | {0}")]
+    UnexpectedValidMessage(&'static str),
+    #[error("Database error: {0}")]
+    Database(#[from] mongodb::error::Error),
+    #[error("Unauthorized | {0}")]
+    Unauthorized(#[from] infra::auth::AuthError),
+    #[error("Lobby error | {0}")]
+    Lobby(#[from] LobbyError),
+    #[error("Unsupported protocol version {0}")]
+    UnsupportedProtocol(u32),
+    #[error("Capability {0} was not negotiated")]
+    MissingCapability(&'static str),
+    #[error("No handshake message in time")]
+    HandshakeTimeout,
+}
+
+impl ManagerError {
+    pub fn close_code(&self) -> u16 {
+        match self {
+            ManagerError::PlayerDisconnected(_) => 1001,
+            ManagerError::InvalidWebsocketMessageType => 1003,
+            ManagerError::Lobby(_) => 1008,
+            ManagerError::Turn(_) | ManagerError::Bid(_) => 1008,
+            ManagerError::UnexpectedJsonMessage(_) => 1008,
+            ManagerError::UnexpectedValidMessage(_) => 1008,
+            ManagerError::MissingCapability(_) => 1008,
+            ManagerError::Database(_) => 1011,
+            ManagerError::Unauthorized(_) => 3000,
+            ManagerError::UnsupportedProtocol(_) => UNSUPPORTED_PROTOCOL,
+            ManagerError::HandshakeTimeout => HEARTBEAT_TIMEOUT,
+        }
+    }
+
+    /// Stable name of the innermost error, for clients to branch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            ManagerError::Turn(e) => e.into(),
+            ManagerError::Bid(e) => e.into(),
+            ManagerError::Lobby(LobbyError::GameError(GameError::InvalidTurn(e))) => e.into(),
+            ManagerError::Lobby(LobbyError::GameError(GameError::InvalidBid(e))) => e.into(),
+            ManagerError::Lobby(LobbyError::GameError(e)) => e.into(),
+            ManagerError::Lobby(e) => e.into(),
+            e => e.into(),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug, strum_macros::IntoStaticStr)]
+pub enum LobbyError {
+    #[error("Invalid lobby id")]
+    InvalidLobby,
+    #[error("This lobby is already playing")]
+    GameAlreadyStarted,
+    #[error("Game didn't started yet")]
+    GameNotStarted,
+    #[error("Game didn't end yet")]
+    GameNotEnded,
+    #[error("This is not your lobby")]
+    WrongLobby,
+    #[error("Hints are disabled in this lobby")]
+    HintsDisabled,
+    #[error("It's not your turn")]
+    NotYourTurn,
+    #[error("Invalid lobby settings | {0}")]
+    InvalidSettings(&'static str),
+    #[error("Only the lobby host can do this")]
+    NotHost,
+    #[error("Player is not in this lobby")]
+    PlayerNotFound,
+    #[error("You were kicked from this lobby")]
+    Kicked,
+    #[error("This lobby can't take more spectators")]
+    SpectatorsFull,
+    #[error("You are already a player in this lobby")]
+    AlreadyInLobby,
+    #[error("This lobby has no stream feed")]
+    StreamDisabled,
+    #[error("Invalid chat message | {0}")]
+    InvalidChatMessage(&'static str),
+    #[error("You are sending messages too fast")]
+    ChatRateLimited,
+    #[error("Wait a moment before reacting again")]
+    ReactionCooldown,
+    #[error("You already host too many lobbies")]
+    TooManyLobbies,
+    #[error("This lobby is private")]
+    PrivateLobby,
+    #[error("Invalid or revoked invite")]
+    InvalidInvite,
+    #[error("This lobby is full")]
+    LobbyFull,
+    #[error("You are already playing in another lobby")]
+    AlreadyInGame,
+    #[error("You are already joining another lobby")]
+    JoinInProgress,
+    #[error("Couldn't work out a move")]
+    SimulationFailed,
+    #[error("Game error | {0}")]
+    GameError(#[from] GameError),
+}
+
+struct InnerManager {
+    lobby: Mutex<LobbiesManager>,
+    connections: SharedConnections,
+    connection_count: AtomicUsize,
+    sessions: SessionConfig,
+    requests: Mutex<HashMap<PlayerId, RequestLog>>,
+}
+
+/// Replies to the latest client message ids of a player
+#[derive(Default)]
+struct RequestLog {
+    order: VecDeque<String>,
+    replies: HashMap<String, RequestEntry>,
+}
+
+enum RequestEntry {
+    /// Still being handled, with the connections that repeated the id in the meantime
+    Pending(Vec<ConnectionId>),
+    Done(ServerMessage),
+}
+
+pub enum RequestState {
+    New,
+    /// The reply goes out once the first attempt finishes
+    Pending,
+    Done(ServerMessage),
+}
+
+impl RequestLog {
+    fn start(&mut self, request_id: &str, connection_id: ConnectionId) -> RequestState {
+        match self.replies.get_mut(request_id) {
+            Some(RequestEntry::Done(reply)) => return RequestState::Done(reply.clone()),
+            Some(RequestEntry::Pending(waiting)) => {
+                waiting.push(connection_id);
+                return RequestState::Pending;
+            }
+            None => {}
+        }
+
+        self.replies
+            .insert(request_id.to_string(), RequestEntry::Pending(Vec::new()));
+        self.order.push_back(request_id.to_string());
+
+        if self.order.len() > REQUEST_LOG_SIZE {
+            if let Some(oldest) = self.order.pop_front() {
+                self.replies.remove(&oldest);
+            }
+        }
+
+        RequestState::New
+    }
+
+    fn finish(&mut self, request_id: &str, reply: ServerMessage) -> Vec<ConnectionId> {
+        match self.replies.get_mut(request_id) {
+            Some(entry) => match std::mem::replace(entry, RequestEntry::Done(reply)) {
+                RequestEntry::Pending(waiting) => waiting,
+                RequestEntry::Done(_) => Vec::new(),
+            },
+            None => Vec::new(),
+        }
+    }
+}
+
+type Connection = SplitSink<WebSocket, Message>;
+type SharedConnections = Arc<Mutex<HashMap<PlayerId, Vec<PlayerConnection>>>>;
+pub type ConnectionId = usize;
+
+struct PlayerConnection {
+    id: ConnectionId,
+    sender: mpsc::Sender<Message>,
+    writer: AbortHandle,
+    protocol: Protocol,
+}
+
+impl PlayerConnection {
+    fn enqueue(&self, player_id: &str, msg: Message) {
+        match self.sender.try_send(msg) {
+            Ok(_) => {}
+            Err(TrySendError::Full(_)) => {
+                // a client this far behind is treated as gone, the socket handler cleans it up
+                tracing::warn!("Outbound queue full for {player_id}, dropping connection");
+                self.writer.abort();
+            }
+            Err(TrySendError::Closed(_)) => {
+                tracing::debug!("Connection of {player_id} is already closed");
+            }
+        }
+    }
+}
+
+struct LobbiesManager {
+    lobbies: HashMap<String, LobbyHandle>,
+    players_lobby: HashMap<PlayerId, LobbyId>,
+    spectators_lobby: HashMap<PlayerId, LobbyId>,
+    joining: HashSet<PlayerId>,
+}
+
+type LobbyId = String;
+type PlayerId = String;
+type SetInfo = (IndexMap<String, Vec<Card>>, String, Card, Vec<usize>);
+type LobbyCommand = Box<dyn FnOnce(&mut Lobby) + Send>;
+
+#[derive(Clone)]
+struct LobbyHandle {
+    sender: UnboundedSender<LobbyCommand>,
+    listing: watch::Receiver<LobbyListing>,
+}
+
+#[derive(Clone)]
+struct LobbyListing {
+    host: PlayerId,
+    creator: PlayerId,
+    created_at: DateTime<Utc>,
+    player_count: usize,
+    visibility: Visibility,
+    practice: bool,
+    started: bool,
+    running: bool,
+}
+
+struct Lobby {
+    id: LobbyId,
+    creator: PlayerId,
+    created_at: DateTime<Utc>,
+    started_at: Option<DateTime<Utc>>,
+    touched_at: Instant,
+    ended_at: Option<Instant>,
+    host: PlayerId,
+    players: IndexMap<String, PlayerStatus>,
+    kicked: HashSet<PlayerId>,
+    invites: HashSet<String>,
+    rematch: HashSet<PlayerId>,
+    spectators: HashSet<PlayerId>,
+    stream: Option<StreamFeed>,
+    chat_history: HashMap<PlayerId, VecDeque<Instant>>,
+    last_reaction: HashMap<PlayerId, Instant>,
+    bots: HashSet<PlayerId>,
+    away: HashMap<PlayerId, Instant>,
+    practice: bool,
+    settings: LobbySettings,
+    state: LobbyState,
+    turn: usize,
+    turn_deadline: Option<Instant>,
+    seq: u64,
+    outbox: Vec<Outgoing>,
+    history: VecDeque<(u64, Vec<Outgoing>)>,
+}
+
+#[derive(Clone)]
+enum Outgoing {
+    Broadcast(Vec<PlayerId>, ServerMessage),
+    Unicast(PlayerId, ServerMessage),
+    Hands(StreamHands),
+    Replay(PlayerId, Vec<EventBatch>),
+    Archive(GameDto),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LobbySettings {
+    #[serde(default)]
+    pub hints: bool,
+    #[serde(default)]
+    pub turn_seconds: Option<u64>,
+    #[serde(default)]
+    pub max_players: Option<usize>,
+    #[serde(default)]
+    pub rotate_seats: bool,
+    #[serde(default)]
+    pub max_spectators: Option<usize>,
+    #[serde(default)]
+    pub stream_delay: Option<u64>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default, skip_serializing)]
+    pub password: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Visibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct LobbyCredentials {
+    pub password: Option<String>,
+    pub invite: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug, Default)]
+pub struct StreamParams {
+    #[serde(default)]
+    pub omniscient: bool,
+    #[serde(flatten)]
+    pub credentials: LobbyCredentials,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct InviteDto {
+    pub invite_id: String,
+    pub token: String,
+}
+
+impl LobbySettings {
+    fn validate(&self) -> Result<(), LobbyError> {
+        if self.turn_seconds.is_some_and(|s| s < MIN_TURN_SECONDS) {
+            return Err(LobbyError::InvalidSettings("turn timer is too short"));
+        }
+
+        if self
+            .max_players
+            .is_some_and(|m| !(2..=MAX_PLAYER_COUNT).contains(&m))
+        {
+            return Err(LobbyError::InvalidSettings("max_players is out of range"));
+        }
+
+        if self.max_spectators.is_some_and(|m| m > MAX_SPECTATORS) {
+            return Err(LobbyError::InvalidSettings("max_spectators is too high"));
+        }
+
+        if self
+            .stream_delay
+            .is_some_and(|d| !(MIN_STREAM_DELAY_SECONDS..=MAX_STREAM_DELAY_SECONDS).contains(&d))
+        {
+            return Err(LobbyError::InvalidSettings("stream delay is out of range"));
+        }
+
+        if self.password.as_ref().is_some_and(|p| p.is_empty()) {
+            return Err(LobbyError::InvalidSettings("password can't be empty"));
+        }
+
+        if self.password.is_some() && self.visibility != Visibility::Private {
+            return Err(LobbyError::InvalidSettings(
+                "only private lobbies can have a password",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The password is never sent back to the host, so an update without one keeps the current one
+    fn keep_password(&mut self, current: &LobbySettings) {
+        if self.password.is_none()
+            && self.visibility == Visibility::Private
+            && current.visibility == Visibility::Private
+        {
+            self.password.clone_from(&current.password);
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.max_players.unwrap_or(MAX_PLAYER_COUNT)
+    }
+
+    fn spectator_capacity(&self) -> usize {
+        self.max_spectators.unwrap_or(DEFAULT_MAX_SPECTATORS)
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct ReaperConfig {
+    interval: Duration,
+    idle_lobby_ttl: Duration,
+    finished_game_ttl: Duration,
+}
+
+impl ReaperConfig {
+    pub fn from_env() -> Self {
+        Self {
+            interval: env_secs("REAPER_INTERVAL_SECS").unwrap_or(DEFAULT_REAPER_INTERVAL),
+            idle_lobby_ttl: env_secs("IDLE_LOBBY_TTL_SECS").unwrap_or(DEFAULT_IDLE_LOBBY_TTL),
+            finished_game_ttl: env_secs("FINISHED_GAME_TTL_SECS")
+                .unwrap_or(DEFAULT_FINISHED_GAME_TTL),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct SessionConfig {
+    /// Closes the older connections of a player when a new one comes in
+    /// instead of delivering to all of them
+    pub take_over: bool,
+    pub ping_interval: Duration,
+    /// How long a connection may stay silent, pongs included, before it is dropped
+    pub pong_timeout: Duration,
+}
+
+impl SessionConfig {
+    pub fn from_env() -> Self {
+        Self {
+            take_over: env_flag("SESSION_TAKEOVER").unwrap_or(false),
+            ping_interval: env_secs("PING_INTERVAL_SECS").unwrap_or(DEFAULT_PING_INTERVAL),
+            pong_timeout: env_secs("PONG_TIMEOUT_SECS").unwrap_or(DEFAULT_PONG_TIMEOUT),
+        }
+    }
+}
+
+fn env_flag(key: &str) -> Option<bool> {
+    let value = std::env::var(key).ok()?;
+
+    match value.parse() {
+        Ok(flag) => Some(flag),
+        Err(_) => {
+            tracing::warn!("Ignoring invalid {key} value: {value}");
+            None
+        }
+    }
+}
+
+fn env_secs(key: &str) -> Option<Duration> {
+    let value = std::env::var(key).ok()?;
+
+    match value.parse() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => {
+            tracing::warn!("Ignoring invalid {key} value: {value}");
+            None
+        }
+    }
+}
+
+struct PendingTurn {
+    lobby_id: LobbyId,
+    player_id: PlayerId,
+    turn: usize,
+    auto_play: AutoPlay,
+}
+
+#[derive(Clone, Copy)]
+enum AutoPlay {
+    Bot,
+    Timeout(Duration),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PlayerStatus {
+    pub ready: bool,
+    pub player: UserClaims,
+    pub online: bool,
+}
+impl PlayerStatus {
+    fn new(claims: UserClaims) -> Self {
+        Self {
+            ready: false,
+            player: claims,
+            online: true,
+        }
     }
 }
 
-#[derive(thiserror::Error, Debug)]
-pub enum ManagerError {
-    #[error("Player disconnected | {0}")]
-    PlayerDisconnected(String),
-    #[error("Error processing turn: {0:?}")]
-    Turn(#[from] TurnError),
-    #[error("Error processing bid: {0:?}")]
-    Bid(#[from] BiddingError),
-    #[error("Invalid websocket message type")]
-    InvalidWebsocketMessageType,
-    #[error("Unexpected valid json message: {0}")]
-    UnexpectedJsonMessage(#[from] serde_json::error::Error),
-    #[error("Unexpected message | {0}")]
-    UnexpectedValidMessage(&'static str),
-    #[error("Database error: {0}")]
-    Database(#[from] mongodb::error::Error),
-    #[error("Unauthorized | {0}")]
-    Unauthorized(#[from] infra::auth::AuthError),
-    #[error("Lobby error | {0}")]
-    Lobby(#[from] LobbyError),
-}
+impl Lobby {
+    fn new(id: LobbyId, creator: PlayerId, settings: LobbySettings) -> Self {
+        Self {
+            id,
+            host: creator.clone(),
+            creator,
+            created_at: Utc::now(),
+            started_at: None,
+            touched_at: Instant::now(),
+            ended_at: None,
+            players: IndexMap::new(),
+            kicked: HashSet::new(),
+            invites: HashSet::new(),
+            rematch: HashSet::new(),
+            spectators: HashSet::new(),
+            stream: None,
+            chat_history: HashMap::new(),
+            last_reaction: HashMap::new(),
+            bots: HashSet::new(),
+            away: HashMap::new(),
+            practice: false,
+            settings,
+            state: LobbyState::NotStarted(HashSet::new()),
+            turn: 0,
+            turn_deadline: None,
+            seq: 0,
+            outbox: Vec::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    fn new_practice(id: LobbyId, user_claims: UserClaims, bots: usize) -> Result<Self, LobbyError> {
+        let host = user_claims.id();
+
+        let bots = (1..=bots).map(|i| UserClaims::Bot(BotClaims::new(i)));
+
+        let players: IndexMap<_, _> = std::iter::once(user_claims)
+            .chain(bots)
+            .map(|c| {
+                (
+                    c.id(),
+                    PlayerStatus {
+                        ready: true,
+                        player: c,
+                        online: true,
+                    },
+                )
+            })
+            .collect();
+
+        let bots = players
+            .values()
+            .filter(|p| matches!(p.player, UserClaims::Bot(_)))
+            .map(|p| p.player.id())
+            .collect();
+
+        let mut lobby = Self {
+            id,
+            creator: host.clone(),
+            created_at: Utc::now(),
+            started_at: None,
+            touched_at: Instant::now(),
+            ended_at: None,
+            host,
+            players,
+            kicked: HashSet::new(),
+            invites: HashSet::new(),
+            rematch: HashSet::new(),
+            spectators: HashSet::new(),
+            stream: None,
+            chat_history: HashMap::new(),
+            last_reaction: HashMap::new(),
+            bots,
+            away: HashMap::new(),
+            practice: true,
+            settings: LobbySettings {
+                hints: true,
+                turn_seconds: None,
+                max_players: None,
+                rotate_seats: false,
+                max_spectators: None,
+                stream_delay: None,
+                visibility: Visibility::Private,
+                password: None,
+            },
+            state: LobbyState::NotStarted(HashSet::new()),
+            turn: 0,
+            turn_deadline: None,
+            seq: 0,
+            outbox: Vec::new(),
+            history: VecDeque::new(),
+        };
+
+        // delivered as the lobby's first batch once its actor is up
+        let set_info = lobby.start()?;
+        lobby.init_set(set_info);
+
+        Ok(lobby)
+    }
+
+    fn start_turn(&mut self) -> Option<PendingTurn> {
+        self.turn += 1;
+        self.turn_deadline = None;
+
+        let player_id = match &self.state {
+            LobbyState::NotStarted(_) => return None,
+            LobbyState::Playing(g) => g.get_current_player()?,
+        };
+
+        let auto_play = if self.bots.contains(&player_id) {
+            AutoPlay::Bot
+        } else {
+            let timeout = Duration::from_secs(self.settings.turn_seconds?);
+            self.turn_deadline = Some(Instant::now() + timeout);
+
+            let msg = ServerMessage::TurnTimer {
+                player_id: player_id.clone(),
+                remaining: timeout.as_secs(),
+            };
+            self.broadcast(msg);
+
+            AutoPlay::Timeout(timeout)
+        };
+
+        Some(PendingTurn {
+            lobby_id: self.id.clone(),
+            player_id,
+            turn: self.turn,
+            auto_play,
+        })
+    }
+
+    fn play_card(
+        &mut self,
+        player_id: PlayerId,
+        card: Card,
+    ) -> Result<Option<PendingTurn>, LobbyError> {
+        if !self.players.contains_key(&player_id) {
+            return Err(LobbyError::WrongLobby);
+        }
+
+        let game = self.get_game()?;
+
+        let turn = Turn { player_id, card };
+
+        let state = game
+            .deal(turn)
+            .map_err(|e| LobbyError::GameError(GameError::InvalidTurn(e)))?;
+
+        self.send_deal_state(state);
+
+        Ok(self.start_turn())
+    }
+
+    fn place_bid(
+        &mut self,
+        player_id: PlayerId,
+        bid: usize,
+    ) -> Result<Option<PendingTurn>, LobbyError> {
+        let game = self.get_game()?;
+
+        let state = game
+            .bid(&player_id, bid)
+            .map_err(|e| LobbyError::GameError(GameError::InvalidBid(e)))?;
+
+        self.broadcast(ServerMessage::PlayerBidded { player_id, bid });
+
+        self.send_bidding_state(state);
+
+        Ok(self.start_turn())
+    }
+
+    fn is_current_player(&self, player_id: &str) -> bool {
+        match &self.state {
+            LobbyState::NotStarted(_) => false,
+            LobbyState::Playing(g) => g.get_current_player().is_some_and(|p| p == player_id),
+        }
+    }
+
+    fn get_turn_timer(&self) -> Option<ServerMessage> {
+        let deadline = self.turn_deadline?;
+
+        let player_id = match &self.state {
+            LobbyState::NotStarted(_) => return None,
+            LobbyState::Playing(g) => g.get_current_player()?,
+        };
+
+        Some(ServerMessage::TurnTimer {
+            player_id,
+            remaining: deadline.saturating_duration_since(Instant::now()).as_secs(),
+        })
+    }
+
+    fn try_start(&mut self) -> Result<Option<SetInfo>, LobbyError> {
+        let should_start = match &self.state {
+            LobbyState::NotStarted(ready) => ready.len() == self.players.len(),
+            LobbyState::Playing(_) => false,
+        };
+
+        if !should_start {
+            return Ok(None);
+        }
+
+        self.start().map(Some)
+    }
+
+    fn start(&mut self) -> Result<SetInfo, LobbyError> {
+        if let LobbyState::Playing(_) = self.state {
+            return Err(LobbyError::GameAlreadyStarted);
+        }
+
+        let game = Game::new_default(self.get_players_id())?;
+
+        let (decks, upcard) = game.get_decks();
+
+        let first = game.get_bidding_player();
+
+        let possible = game.get_possible_bids();
+
+        self.state = LobbyState::Playing(game);
+        self.started_at = Some(Utc::now());
+
+        Ok((decks, first, upcard, possible))
+    }
+
+    fn expired(&self, now: Instant, config: &ReaperConfig) -> Option<LobbyClosedReason> {
+        match &self.state {
+            LobbyState::NotStarted(_) => (now.duration_since(self.touched_at)
+                >= config.idle_lobby_ttl)
+                .then_some(LobbyClosedReason::Idle),
+            LobbyState::Playing(g) if g.is_ended() => {
+                let ended_at = self.ended_at?;
+
+                (now.duration_since(ended_at) >= config.finished_game_ttl)
+                    .then_some(LobbyClosedReason::GameFinished)
+            }
+            LobbyState::Playing(_) => None,
+        }
+    }
+
+    /// Records the end right away so the archive doesn't depend on the lobby being reaped
+    fn end_game(&mut self, winner: Option<String>, lifes: PlayerPoints) {
+        self.broadcast(ServerMessage::GameEnded { winner, lifes });
+
+        self.ended_at = Some(Instant::now());
+
+        if let Some(game) = self.get_archive() {
+            self.outbox.push(Outgoing::Archive(game));
+        }
+    }
+
+    fn get_archive(&self) -> Option<GameDto> {
+        let game = match &self.state {
+            LobbyState::Playing(g) if !self.practice && g.is_ended() => g,
+            _ => return None,
+        };
+
+        // players who forfeited are gone from the lobby but are still part of the game
+        Some(GameDto::new(
+            self.id.clone(),
+            game.get_players_id(),
+            game.get_winner(),
+            game.get_lifes(),
+            self.started_at?,
+            Utc::now(),
+        ))
+    }
+
+    fn check_chat_rate(&mut self, player_id: &str) -> Result<(), LobbyError> {
+        let now = Instant::now();
+
+        let history = self.chat_history.entry(player_id.to_string()).or_default();
+
+        while history
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= CHAT_RATE_WINDOW)
+        {
+            history.pop_front();
+        }
+
+        if history.len() >= CHAT_RATE_LIMIT {
+            return Err(LobbyError::ChatRateLimited);
+        }
+
+        history.push_back(now);
+
+        Ok(())
+    }
+
+    fn check_reaction_cooldown(&mut self, player_id: &str) -> Result<(), LobbyError> {
+        let now = Instant::now();
+
+        if let Some(last) = self.last_reaction.get(player_id) {
+            if now.duration_since(*last) < REACTION_COOLDOWN {
+                return Err(LobbyError::ReactionCooldown);
+            }
+        }
+
+        self.last_reaction.insert(player_id.to_string(), now);
+
+        Ok(())
+    }
+
+    fn check_host(&self, player_id: &str) -> Result<(), LobbyError> {
+        match self.host == player_id {
+            true => Ok(()),
+            false => Err(LobbyError::NotHost),
+        }
+    }
+
+    fn check_access(
+        &self,
+        player_id: &str,
+        credentials: &LobbyCredentials,
+    ) -> Result<(), LobbyError> {
+        if self.settings.visibility != Visibility::Private || self.host == player_id {
+            return Ok(());
+        }
+
+        if let Some(token) = &credentials.invite {
+            let claims = auth::get_invite_claims(token).map_err(|_| LobbyError::InvalidInvite)?;
+
+            return match claims.lobby_id == self.id && self.invites.contains(&claims.invite_id) {
+                true => Ok(()),
+                false => Err(LobbyError::InvalidInvite),
+            };
+        }
+
+        match (&self.settings.password, &credentials.password) {
+            (Some(expected), Some(given)) if expected == given => Ok(()),
+            _ => Err(LobbyError::PrivateLobby),
+        }
+    }
+
+    fn reassign_host(&mut self, leaving: &str) -> Option<PlayerId> {
+        if self.host != leaving {
+            return None;
+        }
+
+        let next = self
+            .players
+            .values()
+            .find(|p| !matches!(p.player, UserClaims::Bot(_)) && p.player.id() != leaving)?;
+
+        self.host = next.player.id();
+
+        Some(self.host.clone())
+    }
+
+    fn mark_away(&mut self, player_id: PlayerId) -> Instant {
+        let msg = ServerMessage::PlayerDisconnected {
+            player_id: player_id.clone(),
+        };
+        self.broadcast(msg);
+
+        let now = Instant::now();
+        self.away.insert(player_id, now);
+
+        now
+    }
+
+    fn mark_back(&mut self, player_id: PlayerId) {
+        if self.away.remove(&player_id).is_none() {
+            return;
+        }
+
+        // outside a running game the seat comes back without a resync
+        if !self.is_running() {
+            self.bots.remove(&player_id);
+        }
+
+        self.broadcast(ServerMessage::PlayerReconnected { player_id });
+    }
+
+    fn get_info(&self, should_reconnect: bool) -> JoinLobbyDto {
+        JoinLobbyDto {
+            id: self.id.clone(),
+            host: self.host.clone(),
+            players: self.get_players(),
+            settings: self.settings.clone(),
+            spectators: self.spectators.len(),
+            should_reconnect,
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        match &self.state {
+            LobbyState::NotStarted(_) => false,
+            LobbyState::Playing(g) => g.is_ended(),
+        }
+    }
+
+    /// Drops a player from a finished game, which may leave everyone else agreeing on a rematch
+    fn leave_finished(&mut self, player_id: &str) -> Result<Option<PendingTurn>, LobbyError> {
+        self.players.shift_remove(player_id);
+        self.rematch.remove(player_id);
+        self.bots.remove(player_id);
+        self.away.remove(player_id);
+
+        if let Some(host_id) = self.reassign_host(player_id) {
+            self.broadcast(ServerMessage::HostChanged { player_id: host_id });
+        }
+
+        self.start_rematch()
+    }
+
+    fn start_rematch(&mut self) -> Result<Option<PendingTurn>, LobbyError> {
+        let Some(players) = self.try_rematch() else {
+            return Ok(None);
+        };
+
+        self.broadcast(ServerMessage::RematchStarted { players });
+
+        let pending = match self.try_start()? {
+            Some(set_info) => {
+                self.init_set(set_info);
+                self.start_turn()
+            }
+            None => None,
+        };
+
+        Ok(pending)
+    }
+
+    fn try_rematch(&mut self) -> Option<Vec<PlayerStatus>> {
+        let mut humans = self
+            .players
+            .values()
+            .filter(|p| !matches!(p.player, UserClaims::Bot(_)))
+            .map(|p| p.player.id())
+            .peekable();
+
+        humans.peek()?;
+
+        if !humans.all(|id| self.rematch.contains(&id)) {
+            return None;
+        }
+
+        if self.settings.rotate_seats && !self.players.is_empty() {
+            self.players.move_index(0, self.players.len() - 1);
+        }
+
+        let ready = match self.practice {
+            true => self.get_players_id().into_iter().collect(),
+            false => HashSet::new(),
+        };
+
+        for status in self.players.values_mut() {
+            status.ready = self.practice;
+        }
+
+        // seats of players that are still away stay with their bot
+        let players = &self.players;
+        let away = &self.away;
+        self.bots.retain(|id| {
+            away.contains_key(id)
+                || players
+                    .get(id)
+                    .is_some_and(|p| matches!(p.player, UserClaims::Bot(_)))
+        });
+        self.state = LobbyState::NotStarted(ready);
+        self.rematch.clear();
+        self.started_at = None;
+        self.ended_at = None;
+        self.turn_deadline = None;
+        self.touched_at = Instant::now();
+
+        Some(self.get_players())
+    }
+
+    fn is_running(&self) -> bool {
+        match &self.state {
+            LobbyState::NotStarted(_) => false,
+            LobbyState::Playing(g) => !g.is_ended(),
+        }
+    }
+
+    fn has_humans(&self) -> bool {
+        self.players
+            .values()
+            .any(|p| !matches!(p.player, UserClaims::Bot(_)))
+    }
+
+    fn get_players_id(&self) -> Vec<String> {
+        self.players.keys().cloned().collect()
+    }
+
+    fn get_audience(&self) -> Vec<String> {
+        self.players
+            .keys()
+            .chain(self.spectators.iter())
+            .cloned()
+            .collect()
+    }
+
+    fn get_players(&self) -> Vec<PlayerStatus> {
+        self.players
+            .iter()
+            .map(|(id, p)| PlayerStatus {
+                online: !self.away.contains_key(id),
+                ..p.clone()
+            })
+            .collect()
+    }
+
+    fn get_game_info(&mut self, player_id: &str) -> Result<GameInfoDto, LobbyError> {
+        let mut info = self.get_game()?.get_game_info(player_id);
+        self.mark_offline(&mut info.info);
+
+        Ok(info)
+    }
+
+    fn get_spectator_info(&mut self) -> Result<SpectatorInfoDto, LobbyError> {
+        let mut info = self.get_game()?.get_spectator_info();
+        self.mark_offline(&mut info.info);
+
+        Ok(info)
+    }
+
+    fn mark_offline(&self, info: &mut [PlayerInfoDto]) {
+        for player in info {
+            player.online = !self.away.contains_key(&player.id);
+        }
+    }
+
+    fn get_listing(&self) -> LobbyListing {
+        LobbyListing {
+            host: self.host.clone(),
+            creator: self.creator.clone(),
+            created_at: self.created_at,
+            player_count: self.players.len(),
+            visibility: self.settings.visibility,
+            practice: self.practice,
+            started: matches!(self.state, LobbyState::Playing(_)),
+            running: self.is_running(),
+        }
+    }
+
+    fn get_game(&mut self) -> Result<&mut Game, LobbyError> {
+        match self.state.borrow_mut() {
+            LobbyState::NotStarted(_) => Err(LobbyError::GameNotStarted),
+            LobbyState::Playing(g) => Ok(g),
+        }
+    }
+
+    fn broadcast(&mut self, msg: ServerMessage) {
+        let players = self.get_audience();
+
+        self.outbox.push(Outgoing::Broadcast(players, msg));
+    }
+
+    fn unicast(&mut self, player_id: &str, msg: ServerMessage) {
+        self.outbox
+            .push(Outgoing::Unicast(player_id.to_string(), msg));
+    }
+
+    fn init_set(&mut self, (decks, next, upcard, possible_bids): SetInfo) {
+        self.broadcast(ServerMessage::SetStart { upcard });
+
+        if self.stream.is_some() {
+            let hands = decks.clone().into_iter().collect();
+            self.outbox.push(Outgoing::Hands(hands));
+        }
+
+        for (p, deck) in decks {
+            self.unicast(&p, ServerMessage::PlayerDeck(deck));
+        }
+
+        self.broadcast(ServerMessage::PlayerBiddingTurn {
+            player_id: next,
+            possible_bids,
+        });
+    }
+
+    fn send_deal_state(&mut self, state: DealState) {
+        self.broadcast(ServerMessage::TurnPlayed { pile: state.pile });
+
+        match state.event {
+            GameEvent::SetEnded {
+                lifes,
+                upcard,
+                decks,
+                next,
+                possible,
+            } => {
+                self.broadcast(ServerMessage::SetEnded { lifes });
+
+                self.init_set((decks, next, upcard, possible));
+            }
+            GameEvent::RoundEnded { rounds, next } => {
+                self.broadcast(ServerMessage::RoundEnded(rounds));
+
+                self.broadcast(ServerMessage::PlayerTurn { player_id: next });
+            }
+            GameEvent::TurnPlayed { next } => {
+                self.broadcast(ServerMessage::PlayerTurn { player_id: next });
+            }
+            GameEvent::Ended { winner, lifes } => self.end_game(winner, lifes),
+        }
+    }
+
+    fn send_bidding_state(&mut self, state: BiddingState) {
+        let msg = match state {
+            BiddingState::Active {
+                possible_bids,
+                next,
+            } => ServerMessage::PlayerBiddingTurn {
+                player_id: next,
+                possible_bids,
+            },
+            BiddingState::Ended { next } => ServerMessage::PlayerTurn { player_id: next },
+        };
+
+        self.broadcast(msg);
+    }
+
+    fn try_replay(&mut self, player_id: &str, last_seen: Option<(LobbyId, u64)>) -> bool {
+        let replay = last_seen
+            .filter(|(lobby_id, _)| *lobby_id == self.id)
+            .and_then(|(_, last_seq)| self.get_replay(player_id, last_seq));
+
+        match replay {
+            Some(batches) => {
+                let player_id = player_id.to_string();
+                self.outbox.push(Outgoing::Replay(player_id, batches));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn get_replay(&self, player_id: &str, last_seq: u64) -> Option<Vec<EventBatch>> {
+        let oldest = self.history.front().map_or(self.seq + 1, |(seq, _)| *seq);
+
+        if last_seq > self.seq || last_seq + 1 < oldest {
+            return None;
+        }
+
+        let batches = self
+            .history
+            .iter()
+            .filter(|(seq, _)| *seq > last_seq)
+            .map(|(seq, outgoing)| EventBatch {
+                lobby_id: self.id.clone(),
+                seq: *seq,
+                events: outgoing
+                    .iter()
+                    .filter_map(|o| match o {
+                        Outgoing::Broadcast(players, msg)
+                            if players.iter().any(|p| p == player_id) =>
+                        {
+                            Some(msg.clone())
+                        }
+                        Outgoing::Unicast(p, msg) if p == player_id => Some(msg.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Some(batches)
+    }
+
+    /// Only batches with broadcasts move the sequence forward and are kept for replays
+    fn record_history(&mut self) {
+        if !self
+            .outbox
+            .iter()
+            .any(|o| matches!(o, Outgoing::Broadcast(..)))
+        {
+            return;
+        }
+
+        self.seq += 1;
+
+        let replayable = self
+            .outbox
+            .iter()
+            .filter(|o| matches!(o, Outgoing::Broadcast(..) | Outgoing::Unicast(..)))
+            .cloned()
+            .collect();
+
+        self.history.push_back((self.seq, replayable));
 
-#[derive(thiserror::Error, Debug)]
-pub enum LobbyError {
-    #[error("Invalid lobby id")]
-    InvalidLobby,
-    #[error("This lobby is already playing")]
-    GameAlreadyStarted,
-    #[error("Game didn't started yet")]
-    GameNotStarted,
-    #[error("This is not your lobby")]
-    WrongLobby,
-    #[error("Game error | {0}")]
-    GameError(#[from] GameError),
-}
+        if self.history.len() > REPLAY_BUFFER_SIZE {
+            self.history.pop_front();
+        }
+    }
 
-struct InnerManager {
-    lobby: Mutex<LobbiesManager>,
-    connections: Mutex<HashMap<String, Connection>>,
-}
+    /// Delivers everything the last command produced as a single batch per recipient
+    /// (unwrapped for connections without `batch`)
+    async fn flush(&mut self, connections: &SharedConnections, games: &GamesRepository) {
+        if self.outbox.is_empty() {
+            return;
+        }
 
-type Connection = SplitSink<WebSocket, Message>;
+        self.record_history();
 
-struct LobbiesManager {
-    lobbies: HashMap<String, Lobby>,
-    players_lobby: HashMap<PlayerId, LobbyId>,
-}
+        let mut replays = Vec::new();
+        let mut batches: IndexMap<PlayerId, Vec<ServerMessage>> = IndexMap::new();
 
-type LobbyId = String;
-type PlayerId = String;
+        for outgoing in std::mem::take(&mut self.outbox) {
+            match outgoing {
+                Outgoing::Broadcast(players, msg) => {
+                    if let Some(feed) = &self.stream {
+                        _ = feed.sender.send(StreamEvent::Message(msg.clone()));
+                    }
 
-struct Lobby {
-    players: IndexMap<String, PlayerStatus>,
-    state: LobbyState,
-}
+                    for p in players {
+                        batches.entry(p).or_default().push(msg.clone());
+                    }
+                }
+                Outgoing::Unicast(p, msg) => batches.entry(p).or_default().push(msg),
+                Outgoing::Hands(hands) => {
+                    if let Some(feed) = &self.stream {
+                        _ = feed.sender.send(StreamEvent::Hands(hands));
+                    }
+                }
+                Outgoing::Replay(p, missed) => replays.push((p, missed)),
+                Outgoing::Archive(game) => {
+                    tokio::spawn(archive_game(games.clone(), game));
+                }
+            }
+        }
 
-#[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
-pub struct PlayerStatus {
-    pub ready: bool,
-    pub player: UserClaims,
-}
-impl PlayerStatus {
-    fn new(claims: UserClaims) -> Self {
-        Self {
-            ready: false,
-            player: claims,
+        let connections = connections.lock().await;
+
+        for (player_id, missed) in replays {
+            let Some(connection) = connections.get(&player_id) else {
+                continue;
+            };
+
+            for batch in missed {
+                send_batch(batch, &player_id, connection);
+            }
+        }
+
+        for (player_id, events) in batches {
+            let Some(connection) = connections.get(&player_id) else {
+                continue;
+            };
+
+            let batch = EventBatch {
+                lobby_id: self.id.clone(),
+                seq: self.seq,
+                events,
+            };
+
+            send_batch(batch, &player_id, connection);
         }
     }
 }
 
-impl Lobby {
+impl LobbiesManager {
     fn new() -> Self {
         Self {
-            players: IndexMap::new(),
-            state: LobbyState::NotStarted(HashSet::new()),
+            lobbies: HashMap::new(),
+            players_lobby: HashMap::new(),
+            spectators_lobby: HashMap::new(),
+            joining: HashSet::new(),
         }
     }
 
-    fn get_players_id(&self) -> Vec<String> {
-        self.players.keys().cloned().collect()
+    fn get_lobby(&self, lobby_id: &str) -> Result<LobbyHandle, LobbyError> {
+        self.lobbies
+            .get(lobby_id)
+            .cloned()
+            .ok_or(LobbyError::InvalidLobby)
     }
 
-    fn get_players(&self) -> Vec<PlayerStatus> {
-        self.players.values().cloned().collect()
+    fn get_player_lobby(&self, player_id: &str) -> Result<(LobbyId, LobbyHandle), LobbyError> {
+        let lobby_id = self
+            .players_lobby
+            .get(player_id)
+            .ok_or(LobbyError::WrongLobby)?;
+
+        Ok((lobby_id.clone(), self.get_lobby(lobby_id)?))
     }
 
-    fn get_game(&mut self) -> Result<&mut Game, LobbyError> {
-        match self.state.borrow_mut() {
-            LobbyState::NotStarted(_) => Err(LobbyError::GameNotStarted),
-            LobbyState::Playing(g) => Ok(g),
+    fn get_member_lobby(&self, player_id: &str) -> Result<LobbyHandle, LobbyError> {
+        let lobby_id = self
+            .players_lobby
+            .get(player_id)
+            .or_else(|| self.spectators_lobby.get(player_id))
+            .ok_or(LobbyError::WrongLobby)?;
+
+        self.get_lobby(lobby_id)
+    }
+
+    fn get_previous_lobby(
+        &self,
+        player_id: &str,
+        lobby_id: &str,
+    ) -> Result<Option<LobbyId>, LobbyError> {
+        let previous = match self.players_lobby.get(player_id) {
+            Some(previous) if previous != lobby_id => previous,
+            _ => return Ok(None),
+        };
+
+        match self.lobbies.get(previous).map(|l| l.get_listing()) {
+            Some(l) if !l.practice && l.running => Err(LobbyError::AlreadyInGame),
+            Some(_) => Ok(Some(previous.clone())),
+            None => Ok(None),
+        }
+    }
+
+    fn reserve_join(&mut self, player_id: &str) -> Result<(), LobbyError> {
+        match self.joining.insert(player_id.to_string()) {
+            true => Ok(()),
+            false => Err(LobbyError::JoinInProgress),
+        }
+    }
+
+    fn check_owned_lobbies(&self, player_id: &str) -> Result<(), LobbyError> {
+        let owned = self
+            .lobbies
+            .values()
+            .filter(|l| l.get_listing().host == player_id)
+            .count();
+
+        match owned < MAX_OWNED_LOBBIES {
+            true => Ok(()),
+            false => Err(LobbyError::TooManyLobbies),
+        }
+    }
+
+    /// Returns the players that are no longer in any lobby
+    fn remove_player(
+        &mut self,
+        lobby_id: &str,
+        player_id: &str,
+        has_humans: bool,
+    ) -> Vec<PlayerId> {
+        let mut removed = Vec::new();
+
+        if self
+            .players_lobby
+            .get(player_id)
+            .is_some_and(|l| l == lobby_id)
+        {
+            self.players_lobby.remove(player_id);
+            removed.push(player_id.to_string());
+        }
+
+        if !has_humans {
+            removed.extend(self.remove_lobby(lobby_id));
+        }
+
+        removed
+    }
+
+    /// Returns the players that were still in the lobby
+    fn remove_lobby(&mut self, lobby_id: &str) -> Vec<PlayerId> {
+        if self.lobbies.remove(lobby_id).is_none() {
+            return Vec::new();
         }
+
+        let players = self
+            .players_lobby
+            .iter()
+            .filter(|(_, l)| *l == lobby_id)
+            .map(|(p, _)| p.clone())
+            .collect();
+
+        self.players_lobby.retain(|_, l| l != lobby_id);
+        self.spectators_lobby.retain(|_, l| l != lobby_id);
+
+        players
     }
 }
 
-impl LobbiesManager {
-    fn new() -> Self {
-        Self {
-            lobbies: HashMap::new(),
-            players_lobby: HashMap::new(),
+impl LobbyHandle {
+    fn spawn(mut lobby: Lobby, connections: SharedConnections, games: GamesRepository) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel::<LobbyCommand>();
+        let (publisher, listing) = watch::channel(lobby.get_listing());
+
+        // the lobby lives until the routing table and every in flight caller drop their handle
+        tokio::spawn(async move {
+            lobby.flush(&connections, &games).await;
+
+            while let Some(command) = receiver.recv().await {
+                // a panicking command fails its caller but must not take the whole lobby down
+                if panic::catch_unwind(AssertUnwindSafe(|| command(&mut lobby))).is_err() {
+                    tracing::error!("Command panicked in lobby {}", lobby.id);
+                    lobby.outbox.clear();
+                }
+
+                lobby.flush(&connections, &games).await;
+                publisher.send_replace(lobby.get_listing());
+            }
+        });
+
+        Self { sender, listing }
+    }
+
+    async fn call<T, F>(&self, f: F) -> Result<T, LobbyError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Lobby) -> Result<T, LobbyError> + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+
+        let command: LobbyCommand = Box::new(move |lobby| {
+            let result = f(lobby);
+
+            // a failed command must not leak the messages it queued before failing
+            if result.is_err() {
+                lobby.outbox.clear();
+            }
+
+            _ = reply.send(result);
+        });
+
+        self.sender
+            .send(command)
+            .map_err(|_| LobbyError::InvalidLobby)?;
+
+        response.await.map_err(|_| LobbyError::InvalidLobby)?
+    }
+
+    fn get_listing(&self) -> LobbyListing {
+        self.listing.borrow().clone()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+fn generate_lobby_id() -> LobbyId {
+    nanoid::nanoid!(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::infra::{
+        auth::{GoogleUserClaims, JWT_KEY},
+        PROTOCOL_VERSION,
+    };
+
+    use super::*;
+
+    fn human(id: &str) -> UserClaims {
+        UserClaims::Google(GoogleUserClaims {
+            email: id.to_string(),
+            name: id.to_string(),
+            picture: String::new(),
+        })
+    }
+
+    fn lobby_with(players: &[&str], settings: LobbySettings) -> Lobby {
+        let mut lobby = Lobby::new("lobby".to_string(), players[0].to_string(), settings);
+
+        for p in players {
+            lobby
+                .players
+                .insert(p.to_string(), PlayerStatus::new(human(p)));
+        }
+
+        lobby
+    }
+
+    #[test]
+    fn test_practice_lobby() {
+        let lobby = Lobby::new_practice("lobby".to_string(), human("P1"), 2).unwrap();
+        assert!(lobby.is_running());
+        assert_eq!(lobby.players.len(), 3);
+        assert_eq!(lobby.bots.len(), 2);
+
+        assert!(matches!(
+            &lobby.outbox[0],
+            Outgoing::Broadcast(_, ServerMessage::SetStart { .. })
+        ));
+        assert!(lobby
+            .outbox
+            .iter()
+            .any(|o| matches!(o, Outgoing::Unicast(p, ServerMessage::PlayerDeck(_)) if p == "P1")));
+        assert!(matches!(
+            lobby.outbox.last(),
+            Some(Outgoing::Broadcast(players, ServerMessage::PlayerBiddingTurn { .. }))
+                if players.len() == 3
+        ));
+    }
+
+    #[test]
+    fn test_turn_timer() {
+        let settings = LobbySettings {
+            turn_seconds: Some(MIN_TURN_SECONDS - 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(LobbyError::InvalidSettings(_))
+        ));
+
+        let mut lobby = lobby_with(&["P1", "P2"], LobbySettings::default());
+        lobby.start().unwrap();
+        assert!(lobby.start_turn().is_none());
+        assert!(lobby.get_turn_timer().is_none());
+
+        let settings = LobbySettings {
+            turn_seconds: Some(30),
+            ..Default::default()
+        };
+        let mut lobby = lobby_with(&["P1", "P2"], settings);
+        let (_, first, _, _) = lobby.start().unwrap();
+
+        let pending = lobby.start_turn().unwrap();
+        assert_eq!(pending.player_id, first);
+        assert_eq!(pending.turn, 1);
+        assert!(matches!(pending.auto_play, AutoPlay::Timeout(t) if t.as_secs() == 30));
+        assert!(matches!(
+            lobby.get_turn_timer(),
+            Some(ServerMessage::TurnTimer { player_id, remaining }) if player_id == first && remaining <= 30
+        ));
+        assert!(matches!(
+            lobby.outbox.last(),
+            Some(Outgoing::Broadcast(_, ServerMessage::TurnTimer { .. }))
+        ));
+
+        // a move made before the timer fires leaves the pending turn behind
+        let next = lobby.place_bid(first, 0).unwrap().unwrap();
+        assert_eq!(next.turn, pending.turn + 1);
+        assert_eq!(lobby.turn, next.turn);
+    }
+
+    #[test]
+    fn test_bot_takeover() {
+        let settings = LobbySettings {
+            turn_seconds: Some(30),
+            ..Default::default()
+        };
+        let mut lobby = lobby_with(&["P1", "P2"], settings);
+        let (_, first, _, _) = lobby.start().unwrap();
+
+        lobby.bots.insert(first.clone());
+        lobby.outbox.clear();
+
+        let pending = lobby.start_turn().unwrap();
+        assert_eq!(pending.player_id, first);
+        assert!(matches!(pending.auto_play, AutoPlay::Bot));
+        assert!(lobby.turn_deadline.is_none());
+        assert!(lobby.outbox.is_empty());
+
+        // a human seat played by a bot goes back to its owner on a rematch, unless they're away
+        let other = if first == "P1" { "P2" } else { "P1" }.to_string();
+        lobby.bots.insert(other.clone());
+        lobby.away.insert(other.clone(), Instant::now());
+
+        lobby.state = LobbyState::NotStarted(HashSet::new());
+        lobby.rematch.extend(["P1".to_string(), "P2".to_string()]);
+        assert!(lobby.try_rematch().is_some());
+        assert_eq!(lobby.bots, HashSet::from([other]));
+    }
+
+    #[test]
+    fn test_reassign_host() {
+        let mut lobby = lobby_with(&["P1", "P2", "P3"], LobbySettings::default());
+        let bot = UserClaims::Bot(BotClaims::new(1));
+        lobby.players.shift_remove("P2");
+        lobby
+            .players
+            .insert_before(1, bot.id(), PlayerStatus::new(bot));
+        assert!(lobby.check_host("P1").is_ok());
+        assert!(matches!(lobby.check_host("P3"), Err(LobbyError::NotHost)));
+
+        assert_eq!(lobby.reassign_host("P3"), None);
+        assert_eq!(lobby.host, "P1");
+
+        assert_eq!(lobby.reassign_host("P1"), Some("P3".to_string()));
+        assert!(lobby.check_host("P3").is_ok());
+
+        lobby.players.shift_remove("P1");
+        assert_eq!(lobby.reassign_host("P3"), None);
+        assert_eq!(lobby.host, "P3");
+    }
+
+    fn handle(lobby: &Lobby) -> LobbyHandle {
+        let (sender, _) = mpsc::unbounded_channel();
+        let (_, listing) = watch::channel(lobby.get_listing());
+
+        LobbyHandle { sender, listing }
+    }
+
+    #[test]
+    fn test_owned_lobbies() {
+        let mut manager = LobbiesManager::new();
+
+        for _ in 0..MAX_OWNED_LOBBIES {
+            assert!(manager.check_owned_lobbies("P1").is_ok());
+
+            let id = generate_lobby_id();
+            let lobby = Lobby::new(id.clone(), "P1".to_string(), LobbySettings::default());
+            assert_eq!(lobby.get_listing().creator, "P1");
+
+            assert!(manager.lobbies.insert(id, handle(&lobby)).is_none());
+        }
+
+        assert!(matches!(
+            manager.check_owned_lobbies("P1"),
+            Err(LobbyError::TooManyLobbies)
+        ));
+        assert!(manager.check_owned_lobbies("P2").is_ok());
+    }
+
+    #[test]
+    fn test_private_lobby() {
+        JWT_KEY.get_or_init(|| "test".to_string());
+
+        let settings = LobbySettings {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(LobbyError::InvalidSettings(_))
+        ));
+
+        let settings = LobbySettings {
+            visibility: Visibility::Private,
+            ..settings
+        };
+        assert!(settings.validate().is_ok());
+
+        let mut lobby = lobby_with(&["P1"], settings);
+        let no_credentials = LobbyCredentials::default();
+        assert!(lobby.check_access("P1", &no_credentials).is_ok());
+        assert!(matches!(
+            lobby.check_access("P2", &no_credentials),
+            Err(LobbyError::PrivateLobby)
+        ));
+
+        let password = |p: &str| LobbyCredentials {
+            password: Some(p.to_string()),
+            invite: None,
+        };
+        assert!(lobby.check_access("P2", &password("secret")).is_ok());
+        assert!(matches!(
+            lobby.check_access("P2", &password("wrong")),
+            Err(LobbyError::PrivateLobby)
+        ));
+
+        let invite = |lobby_id: &str| LobbyCredentials {
+            password: None,
+            invite: Some(auth::create_invite_token(
+                lobby_id.to_string(),
+                "invite".to_string(),
+            )),
+        };
+        lobby.invites.insert("invite".to_string());
+        assert!(lobby.check_access("P2", &invite("lobby")).is_ok());
+        assert!(matches!(
+            lobby.check_access("P2", &invite("other")),
+            Err(LobbyError::InvalidInvite)
+        ));
+
+        lobby.invites.remove("invite");
+        assert!(matches!(
+            lobby.check_access("P2", &invite("lobby")),
+            Err(LobbyError::InvalidInvite)
+        ));
+
+        // editing other settings doesn't drop the password the host never gets to see
+        let mut update = LobbySettings {
+            hints: true,
+            visibility: Visibility::Private,
+            ..Default::default()
+        };
+        update.keep_password(&lobby.settings);
+        assert_eq!(update.password, Some("secret".to_string()));
+
+        let mut update = LobbySettings::default();
+        update.keep_password(&lobby.settings);
+        assert_eq!(update.password, None);
+    }
+
+    #[test]
+    fn test_join_capacity() {
+        assert_eq!(LobbySettings::default().capacity(), MAX_PLAYER_COUNT);
+
+        for max_players in [1, MAX_PLAYER_COUNT + 1] {
+            let settings = LobbySettings {
+                max_players: Some(max_players),
+                ..Default::default()
+            };
+            assert!(matches!(
+                settings.validate(),
+                Err(LobbyError::InvalidSettings(_))
+            ));
+        }
+
+        let settings = LobbySettings {
+            max_players: Some(2),
+            ..Default::default()
+        };
+        assert!(settings.validate().is_ok());
+        assert_eq!(settings.capacity(), 2);
+
+        let mut manager = LobbiesManager::new();
+        assert!(manager.reserve_join("P1").is_ok());
+        assert!(matches!(
+            manager.reserve_join("P1"),
+            Err(LobbyError::JoinInProgress)
+        ));
+
+        let mut lobby = lobby_with(&["P1", "P2"], settings);
+        manager.lobbies.insert("lobby".to_string(), handle(&lobby));
+        manager
+            .players_lobby
+            .insert("P1".to_string(), "lobby".to_string());
+        assert_eq!(
+            manager.get_previous_lobby("P1", "other").unwrap(),
+            Some("lobby".to_string())
+        );
+        assert_eq!(manager.get_previous_lobby("P1", "lobby").unwrap(), None);
+
+        lobby.start().unwrap();
+        manager.lobbies.insert("lobby".to_string(), handle(&lobby));
+        assert!(matches!(
+            manager.get_previous_lobby("P1", "other"),
+            Err(LobbyError::AlreadyInGame)
+        ));
+    }
+
+    #[test]
+    fn test_rematch() {
+        let settings = LobbySettings {
+            rotate_seats: true,
+            ..Default::default()
+        };
+        let mut lobby = lobby_with(&["P1", "P2", "P3"], settings);
+        lobby.start().unwrap();
+
+        let game = lobby.get_game().unwrap();
+        game.forfeit("P2").unwrap();
+        game.forfeit("P3").unwrap();
+        assert!(lobby.is_finished());
+
+        lobby.rematch.insert("P1".to_string());
+        assert!(lobby.start_rematch().unwrap().is_none());
+        assert!(lobby.is_finished());
+
+        lobby.rematch.insert("P2".to_string());
+        assert!(lobby.start_rematch().unwrap().is_none());
+        assert!(lobby.is_finished());
+
+        // the missing vote is a decline, which lets the others go ahead
+        lobby.outbox.clear();
+        assert!(lobby.leave_finished("P3").unwrap().is_none());
+        assert!(!lobby.is_finished());
+        assert!(lobby.rematch.is_empty());
+        assert_eq!(lobby.get_players_id(), ["P2", "P1"]);
+        assert!(matches!(
+            lobby.state,
+            LobbyState::NotStarted(ref ready) if ready.is_empty()
+        ));
+        assert!(matches!(
+            &lobby.outbox[..],
+            [Outgoing::Broadcast(_, ServerMessage::RematchStarted { players })]
+                if players.iter().all(|p| !p.ready) && players[0].player.id() == "P2"
+        ));
+    }
+
+    #[test]
+    fn test_spectators() {
+        assert_eq!(
+            LobbySettings::default().spectator_capacity(),
+            DEFAULT_MAX_SPECTATORS
+        );
+
+        let settings = LobbySettings {
+            max_spectators: Some(MAX_SPECTATORS + 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            settings.validate(),
+            Err(LobbyError::InvalidSettings(_))
+        ));
+
+        let mut lobby = lobby_with(&["P1", "P2"], LobbySettings::default());
+        assert!(matches!(
+            lobby.get_spectator_info(),
+            Err(LobbyError::GameNotStarted)
+        ));
+
+        lobby.spectators.insert("S1".to_string());
+        let set_info = lobby.start().unwrap();
+        lobby.init_set(set_info);
+
+        for outgoing in &lobby.outbox {
+            match outgoing {
+                Outgoing::Broadcast(audience, _) => assert!(audience.contains(&"S1".to_string())),
+                Outgoing::Unicast(p, _) => assert_ne!(p, "S1"),
+                _ => {}
+            }
+        }
+
+        assert_eq!(lobby.get_spectator_info().unwrap().info.len(), 2);
+    }
+
+    #[test]
+    fn test_chat_rate() {
+        let mut lobby = lobby_with(&["P1", "P2"], LobbySettings::default());
+
+        for _ in 0..CHAT_RATE_LIMIT {
+            assert!(lobby.check_chat_rate("P1").is_ok());
+        }
+        assert!(matches!(
+            lobby.check_chat_rate("P1"),
+            Err(LobbyError::ChatRateLimited)
+        ));
+        assert!(lobby.check_chat_rate("P2").is_ok());
+
+        // only the messages that left the window free up room
+        let history = lobby.chat_history.get_mut("P1").unwrap();
+        history[0] = Instant::now() - CHAT_RATE_WINDOW;
+        assert!(lobby.check_chat_rate("P1").is_ok());
+        assert!(matches!(
+            lobby.check_chat_rate("P1"),
+            Err(LobbyError::ChatRateLimited)
+        ));
+    }
+
+    #[test]
+    fn test_reaction_cooldown() {
+        let mut lobby = lobby_with(&["P1", "P2"], LobbySettings::default());
+
+        assert!(lobby.check_reaction_cooldown("P1").is_ok());
+        assert!(matches!(
+            lobby.check_reaction_cooldown("P1"),
+            Err(LobbyError::ReactionCooldown)
+        ));
+        assert!(lobby.check_reaction_cooldown("P2").is_ok());
+
+        lobby
+            .last_reaction
+            .insert("P1".to_string(), Instant::now() - REACTION_COOLDOWN);
+        assert!(lobby.check_reaction_cooldown("P1").is_ok());
+        assert!(matches!(
+            lobby.check_reaction_cooldown("P1"),
+            Err(LobbyError::ReactionCooldown)
+        ));
+    }
+
+    fn connection(protocol: Protocol) -> (PlayerConnection, mpsc::Receiver<Message>) {
+        let (sender, receiver) = mpsc::channel(OUTBOUND_QUEUE_SIZE);
+
+        let connection = PlayerConnection {
+            id: 0,
+            sender,
+            writer: tokio::spawn(std::future::pending::<()>()).abort_handle(),
+            protocol,
+        };
+
+        (connection, receiver)
+    }
+
+    #[test]
+    fn test_record_history() {
+        let mut lobby = lobby_with(&["P1", "P2"], LobbySettings::default());
+
+        lobby.unicast("P1", ServerMessage::PlayerDeck(Vec::new()));
+        lobby.record_history();
+        assert_eq!(lobby.seq, 0);
+        assert!(lobby.history.is_empty());
+
+        lobby.broadcast(ServerMessage::PlayerTurn {
+            player_id: "P1".to_string(),
+        });
+        lobby.record_history();
+        assert_eq!(lobby.seq, 1);
+        assert!(matches!(&lobby.history[0], (1, batch) if batch.len() == 2));
+
+        for _ in 0..REPLAY_BUFFER_SIZE {
+            lobby.record_history();
+        }
+        assert_eq!(lobby.seq, REPLAY_BUFFER_SIZE as u64 + 1);
+        assert_eq!(lobby.history.len(), REPLAY_BUFFER_SIZE);
+        assert_eq!(lobby.history.front().map(|(seq, _)| *seq), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_send_batch() {
+        let (legacy, mut legacy_receiver) = connection(Protocol::legacy());
+        let protocol = Protocol::negotiate(PROTOCOL_VERSION, vec![BATCH.to_string()]);
+        let (batched, mut batched_receiver) = connection(protocol);
+
+        let batch = EventBatch {
+            lobby_id: "lobby".to_string(),
+            seq: 3,
+            events: vec![
+                ServerMessage::PlayerDeck(Vec::new()),
+                ServerMessage::PlayerTurn {
+                    player_id: "P1".to_string(),
+                },
+            ],
+        };
+        send_batch(batch.clone(), "P1", &[legacy, batched]);
+
+        for msg in &batch.events {
+            assert_eq!(legacy_receiver.try_recv().ok(), Some(to_text(msg, "P1")));
+        }
+        assert!(legacy_receiver.try_recv().is_err());
+
+        let msg = to_text(&ServerMessage::Batch(batch), "P1");
+        assert_eq!(batched_receiver.try_recv().ok(), Some(msg));
+        assert!(batched_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut lobby = lobby_with(&["P1", "P2"], LobbySettings::default());
+        let turn = |player_id: &str| ServerMessage::PlayerTurn {
+            player_id: player_id.to_string(),
+        };
+
+        for player_id in ["P1", "P2", "P1"] {
+            lobby.broadcast(turn(player_id));
+            lobby.unicast(player_id, ServerMessage::PlayerDeck(Vec::new()));
+            lobby.record_history();
+            lobby.outbox.clear();
+        }
+
+        assert_eq!(lobby.get_replay("P1", 3), Some(Vec::new()));
+        assert_eq!(lobby.get_replay("P1", 4), None);
+
+        let batches = lobby.get_replay("P2", 1).unwrap();
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[0].seq, 2);
+        assert_eq!(
+            batches[0].events,
+            [turn("P2"), ServerMessage::PlayerDeck(Vec::new())]
+        );
+        assert_eq!(batches[1].events, [turn("P1")]);
+        assert_eq!(lobby.get_replay("P2", 0).map(|b| b.len()), Some(3));
+
+        // once the gap is older than the buffer only a full reconnect can catch up
+        for _ in 0..REPLAY_BUFFER_SIZE {
+            lobby.broadcast(turn("P2"));
+            lobby.record_history();
+            lobby.outbox.clear();
         }
+        assert_eq!(lobby.get_replay("P2", 2), None);
+        assert!(lobby.get_replay("P2", 3).is_some());
+
+        assert!(!lobby.try_replay("P2", None));
+        assert!(!lobby.try_replay("P2", Some(("other".to_string(), lobby.seq))));
+        assert!(!lobby.try_replay("P2", Some(("lobby".to_string(), 0))));
+        assert!(lobby.outbox.is_empty());
+
+        assert!(lobby.try_replay("P2", Some(("lobby".to_string(), lobby.seq - 1))));
+        assert!(matches!(
+            &lobby.outbox[..],
+            [Outgoing::Replay(p, batches)] if p == "P2" && batches.len() == 1
+        ));
+    }
+
+    #[test]
+    fn test_request_log() {
+        let mut log = RequestLog::default();
+        let ack = ServerMessage::Ack {
+            id: "1".to_string(),
+        };
+
+        assert!(matches!(log.start("1", 0), RequestState::New));
+        assert!(matches!(log.start("1", 1), RequestState::Pending));
+        assert!(matches!(log.start("1", 2), RequestState::Pending));
+
+        assert_eq!(log.finish("1", ack.clone()), [1, 2]);
+        assert!(matches!(log.start("1", 3), RequestState::Done(reply) if reply == ack));
+        assert!(log.finish("1", ack.clone()).is_empty());
+        assert!(log.finish("unknown", ack).is_empty());
+
+        for id in 2..=REQUEST_LOG_SIZE {
+            assert!(matches!(log.start(&id.to_string(), 0), RequestState::New));
+        }
+        assert_eq!(log.replies.len(), REQUEST_LOG_SIZE);
+
+        assert!(matches!(log.start("0", 0), RequestState::New));
+        assert_eq!(log.replies.len(), REQUEST_LOG_SIZE);
+        assert!(matches!(log.start("1", 0), RequestState::New));
+    }
+
+    #[test]
+    fn test_archive() {
+        let mut lobby = lobby_with(&["P1", "P2", "P3"], LobbySettings::default());
+        assert!(lobby.get_archive().is_none());
+
+        lobby.start().unwrap();
+
+        for player_id in ["P2", "P3"] {
+            lobby.get_game().unwrap().forfeit(player_id).unwrap();
+            lobby.players.shift_remove(player_id);
+        }
+
+        let game = serde_json::to_value(lobby.get_archive().unwrap()).unwrap();
+        assert_eq!(game["players"], serde_json::json!(["P1", "P2", "P3"]));
+        assert_eq!(game["winner"], "P1");
+        assert_eq!(game["lifes"]["P3"], 0);
+
+        lobby.practice = true;
+        assert!(lobby.get_archive().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_outbound_queue_overflow() {
+        let (connection, _receiver) = connection(Protocol::legacy());
+
+        for _ in 0..OUTBOUND_QUEUE_SIZE {
+            connection.enqueue("P1", Message::Ping(Vec::new()));
+        }
+        tokio::task::yield_now().await;
+        assert!(!connection.writer.is_finished());
+
+        // one message too many means the client stopped reading
+        connection.enqueue("P1", Message::Ping(Vec::new()));
+        for _ in 0..10 {
+            tokio::task::yield_now().await;
+        }
+        assert!(connection.writer.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_take_over_sessions() {
+        let multi = Protocol::negotiate(PROTOCOL_VERSION, vec![MULTI_SESSION.to_string()]);
+
+        let taken_over = |receiver: &mut mpsc::Receiver<Message>| {
+            matches!(
+                receiver.try_recv(),
+                Ok(Message::Close(Some(CloseFrame {
+                    code: SESSION_TAKEN_OVER,
+                    ..
+                })))
+            )
+        };
+
+        for (old, incoming, take_over, kept) in [
+            (Protocol::legacy(), Protocol::legacy(), false, false),
+            (multi.clone(), Protocol::legacy(), false, false),
+            (Protocol::legacy(), multi.clone(), false, false),
+            (multi.clone(), multi.clone(), false, true),
+            (multi.clone(), multi.clone(), true, false),
+        ] {
+            let (connection, mut receiver) = connection(old);
+            let mut connections = vec![connection];
+
+            take_over_sessions(&mut connections, "P1", &incoming, take_over);
+
+            assert_eq!(connections.len(), kept as usize);
+            assert_eq!(taken_over(&mut receiver), !kept);
+        }
+    }
+
+    #[test]
+    fn test_presence() {
+        let mut lobby = lobby_with(&["P1", "P2"], LobbySettings::default());
+        lobby.mark_away("P2".to_string());
+        assert!(matches!(
+            &lobby.outbox[..],
+            [Outgoing::Broadcast(_, ServerMessage::PlayerDisconnected { player_id })]
+                if player_id == "P2"
+        ));
+
+        let online = |players: &[PlayerStatus]| -> Vec<_> {
+            players.iter().map(|p| (p.player.id(), p.online)).collect()
+        };
+        let expected = [("P1".to_string(), true), ("P2".to_string(), false)];
+        assert_eq!(online(&lobby.get_info(false).players), expected);
+
+        lobby.start().unwrap();
+        let info = lobby.get_game_info("P1").unwrap();
+        assert!(info.info.iter().all(|p| p.online == (p.id == "P1")));
+
+        lobby.outbox.clear();
+        lobby.mark_back("P2".to_string());
+        lobby.mark_back("P1".to_string());
+        assert!(matches!(
+            &lobby.outbox[..],
+            [Outgoing::Broadcast(_, ServerMessage::PlayerReconnected { player_id })]
+                if player_id == "P2"
+        ));
+        assert!(lobby
+            .get_game_info("P1")
+            .unwrap()
+            .info
+            .iter()
+            .all(|p| p.online));
+        assert!(lobby.get_info(false).players.iter().all(|p| p.online));
     }
 }
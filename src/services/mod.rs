@@ -1,28 +1,43 @@
-use crate::models::Card;
+use std::collections::HashMap;
+
+use crate::models::{Card, Turn};
 
 pub mod manager;
 pub mod repositories;
+pub mod stream;
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct GameInfoDto {
     pub info: Vec<PlayerInfoDto>,
     pub deck: Vec<Card>,
     pub upcard: Card,
-    pub current_player: String,
+    pub pile: Vec<Turn>,
+    pub current_player: Option<String>,
+    pub stage: GameStageDto,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct SpectatorInfoDto {
+    pub info: Vec<PlayerInfoDto>,
+    pub deck_sizes: HashMap<String, usize>,
+    pub upcard: Card,
+    pub pile: Vec<Turn>,
+    pub current_player: Option<String>,
     pub stage: GameStageDto,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 #[serde(tag = "type", content = "data")]
 pub enum GameStageDto {
     Bidding { possible_bids: Vec<usize> },
     Dealing,
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
 pub struct PlayerInfoDto {
     pub id: String,
     pub lifes: usize,
     pub rounds: usize,
     pub bid: Option<usize>,
+    pub online: bool,
 }
@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use chrono::{DateTime, Utc};
 use mongodb::{bson::doc, error::Result, Collection, Database};
 
@@ -30,9 +32,34 @@ impl GamesRepository {
     }
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct GameDto {
+    id: String,
+    players: Vec<String>,
+    winner: Option<String>,
+    lifes: HashMap<String, usize>,
     started_at: DateTime<Utc>,
+    ended_at: DateTime<Utc>,
+}
+
+impl GameDto {
+    pub fn new(
+        id: String,
+        players: Vec<String>,
+        winner: Option<String>,
+        lifes: HashMap<String, usize>,
+        started_at: DateTime<Utc>,
+        ended_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            id,
+            players,
+            winner,
+            lifes,
+            started_at,
+            ended_at,
+        }
+    }
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -0,0 +1,268 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    time::Instant,
+};
+
+use crate::{infra::ServerMessage, models::Card};
+
+use super::manager::Manager;
+
+pub type StreamHands = HashMap<String, Vec<Card>>;
+
+pub enum StreamEvent {
+    Message(ServerMessage),
+    Hands(StreamHands),
+    Viewer { player_id: String, omniscient: bool },
+    ViewerLeft { player_id: String },
+}
+
+pub struct StreamFeed {
+    pub id: String,
+    pub sender: UnboundedSender<StreamEvent>,
+}
+
+impl StreamFeed {
+    pub fn new() -> (Self, UnboundedReceiver<StreamEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        let feed = Self {
+            id: format!("stream-{}", nanoid::nanoid!(10)),
+            sender,
+        };
+
+        (feed, receiver)
+    }
+}
+
+struct PendingHands {
+    due: Instant,
+    // hands are only revealed after their set is over for the live players
+    safe: bool,
+    hands: StreamHands,
+}
+
+struct Relay {
+    delay: Duration,
+    viewers: HashMap<String, bool>,
+    messages: VecDeque<(Instant, ServerMessage)>,
+    hands: VecDeque<PendingHands>,
+}
+
+pub async fn run(
+    manager: Manager,
+    stream_id: String,
+    delay: Duration,
+    mut receiver: UnboundedReceiver<StreamEvent>,
+) {
+    let mut relay = Relay {
+        delay,
+        viewers: HashMap::new(),
+        messages: VecDeque::new(),
+        hands: VecDeque::new(),
+    };
+
+    let mut closed = false;
+
+    while !closed || !relay.is_empty() {
+        let next_due = relay.next_due();
+
+        tokio::select! {
+            event = receiver.recv(), if !closed => match event {
+                Some(event) => relay.push(event, Instant::now()),
+                None => {
+                    closed = true;
+                    relay.hands.iter_mut().for_each(|h| h.safe = true);
+                }
+            },
+            _ = sleep_until(next_due) => relay.deliver(&manager).await,
+        }
+    }
+
+    tracing::debug!("Stream {stream_id} finished");
+}
+
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+impl Relay {
+    fn push(&mut self, event: StreamEvent, now: Instant) {
+        let due = now + self.delay;
+
+        match event {
+            StreamEvent::Message(msg) => {
+                if let ServerMessage::SetEnded { .. } | ServerMessage::GameEnded { .. } = msg {
+                    if let Some(hands) = self.hands.iter_mut().find(|h| !h.safe) {
+                        hands.safe = true;
+                    }
+                }
+
+                self.messages.push_back((due, msg));
+            }
+            StreamEvent::Hands(hands) => self.hands.push_back(PendingHands {
+                due,
+                safe: false,
+                hands,
+            }),
+            StreamEvent::Viewer {
+                player_id,
+                omniscient,
+            } => {
+                self.viewers.insert(player_id, omniscient);
+            }
+            StreamEvent::ViewerLeft { player_id } => {
+                self.viewers.remove(&player_id);
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.messages.is_empty() && self.hands.is_empty()
+    }
+
+    fn next_due(&self) -> Option<Instant> {
+        let message = self.messages.front().map(|(due, _)| *due);
+
+        let hands = self.hands.front().filter(|h| h.safe).map(|h| h.due);
+
+        match (message, hands) {
+            (Some(m), Some(h)) => Some(m.min(h)),
+            (m, h) => m.or(h),
+        }
+    }
+
+    /// Hands of a set go out right before the first message that was delayed after them
+    fn pop_due(&mut self, now: Instant) -> Option<ServerMessage> {
+        let message_due = self.messages.front().is_some_and(|(due, _)| *due <= now);
+
+        let hands_due = self.hands.front().is_some_and(|h| h.safe && h.due <= now);
+
+        let hands_first = hands_due
+            && self
+                .messages
+                .front()
+                .is_none_or(|(due, _)| self.hands[0].due < *due);
+
+        if hands_first {
+            let pending = self.hands.pop_front()?;
+
+            Some(ServerMessage::StreamHands(pending.hands))
+        } else if message_due {
+            self.messages.pop_front().map(|(_, msg)| msg)
+        } else {
+            None
+        }
+    }
+
+    async fn deliver(&mut self, manager: &Manager) {
+        let now = Instant::now();
+
+        while let Some(msg) = self.pop_due(now) {
+            let hands = matches!(msg, ServerMessage::StreamHands(_));
+
+            for (viewer, omniscient) in &self.viewers {
+                if *omniscient || !hands {
+                    manager.unicast_msg(viewer, &msg).await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(player_id: &str) -> StreamEvent {
+        StreamEvent::Message(ServerMessage::PlayerTurn {
+            player_id: player_id.to_string(),
+        })
+    }
+
+    fn hands(player_id: &str) -> StreamEvent {
+        StreamEvent::Hands(HashMap::from([(player_id.to_string(), Vec::new())]))
+    }
+
+    fn set_ended() -> StreamEvent {
+        StreamEvent::Message(ServerMessage::SetEnded {
+            lifes: HashMap::new(),
+        })
+    }
+
+    #[test]
+    fn test_reveal_order() {
+        let delay = Duration::from_secs(10);
+        let mut relay = Relay {
+            delay,
+            viewers: HashMap::new(),
+            messages: VecDeque::new(),
+            hands: VecDeque::new(),
+        };
+
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        relay.push(turn("P1"), at(0));
+        relay.push(hands("P1"), at(1));
+        relay.push(turn("P2"), at(2));
+        assert_eq!(relay.next_due(), Some(at(0) + delay));
+
+        // the hands stay hidden until their set is over, even when they're due
+        assert!(matches!(
+            relay.pop_due(at(20)),
+            Some(ServerMessage::PlayerTurn { player_id }) if player_id == "P1"
+        ));
+        assert!(matches!(
+            relay.pop_due(at(20)),
+            Some(ServerMessage::PlayerTurn { player_id }) if player_id == "P2"
+        ));
+        assert!(relay.pop_due(at(20)).is_none());
+        assert_eq!(relay.next_due(), None);
+        assert!(!relay.is_empty());
+
+        relay.push(set_ended(), at(3));
+        relay.push(hands("P2"), at(4));
+        relay.push(turn("P2"), at(5));
+        assert_eq!(relay.next_due(), Some(at(1) + delay));
+
+        assert!(relay.pop_due(at(10)).is_none());
+        assert!(matches!(
+            relay.pop_due(at(30)),
+            Some(ServerMessage::StreamHands(h)) if h.contains_key("P1")
+        ));
+        assert!(matches!(
+            relay.pop_due(at(30)),
+            Some(ServerMessage::SetEnded { .. })
+        ));
+        assert!(matches!(
+            relay.pop_due(at(30)),
+            Some(ServerMessage::PlayerTurn { player_id }) if player_id == "P2"
+        ));
+        assert!(relay.pop_due(at(30)).is_none());
+
+        relay.push(
+            StreamEvent::Message(ServerMessage::GameEnded {
+                winner: None,
+                lifes: HashMap::new(),
+            }),
+            at(6),
+        );
+        assert!(matches!(
+            relay.pop_due(at(30)),
+            Some(ServerMessage::StreamHands(h)) if h.contains_key("P2")
+        ));
+        assert!(matches!(
+            relay.pop_due(at(30)),
+            Some(ServerMessage::GameEnded { .. })
+        ));
+        assert!(relay.is_empty());
+    }
+}
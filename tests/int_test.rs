@@ -1,13 +1,14 @@
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, VecDeque};
 
     use futures::{stream::FusedStream, SinkExt, StreamExt};
     use oh_hell::{
         infra::{
             auth::{get_claims_from_token, ProfileParams, TokenResponse},
             lobby::CreateLobbyResponse,
-            ClientGameMessage, ClientMessage, JoinLobbyDto, ServerMessage,
+            ClientGameMessage, ClientMessage, JoinLobbyDto, PlayerPoints, ServerMessage, BATCH,
+            PROTOCOL_VERSION,
         },
         models::{Card, MAX_PLAYER_COUNT},
     };
@@ -21,6 +22,8 @@ mod tests {
     async fn test_example() {
         task::spawn(oh_hell::start_app());
 
+        wait_for_server().await;
+
         for p in 2..=MAX_PLAYER_COUNT {
             let mut client = reqwest::Client::new();
 
@@ -28,6 +31,9 @@ mod tests {
 
             let mut player_data = join_lobby(&mut client, tokens).await;
 
+            // eliminated players keep their socket open so nobody sees them disconnecting
+            let mut eliminated = vec![];
+
             ready(&mut player_data).await;
 
             'game: loop {
@@ -35,13 +41,38 @@ mod tests {
 
                 play_set(&mut player_data).await;
 
+                let mut lifes = PlayerPoints::new();
+
                 for p in player_data.values_mut() {
-                    if assert_game_or_set_ended(&mut p.connection).await {
-                        break 'game;
+                    match assert_game_or_set_ended(&mut p.connection).await {
+                        Some(l) => lifes = l,
+                        None => break 'game,
                     }
                 }
+
+                let dead: Vec<_> = player_data
+                    .keys()
+                    .filter(|id| lifes[*id] == 0)
+                    .cloned()
+                    .collect();
+
+                for id in dead {
+                    eliminated.extend(player_data.remove(&id));
+                }
+            }
+        }
+    }
+
+    async fn wait_for_server() {
+        for _ in 0..50 {
+            if TcpStream::connect("localhost:3000").await.is_ok() {
+                return;
             }
+
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
         }
+
+        panic!("Server didn't start");
     }
 
     async fn get_players(client: &mut Client, count: usize) -> Vec<String> {
@@ -55,22 +86,22 @@ mod tests {
         players
     }
 
-    async fn assert_game_or_set_ended(socket: &mut WebSocket) -> bool {
+    async fn assert_game_or_set_ended(socket: &mut WebSocket) -> Option<PlayerPoints> {
         match recv_msg(socket).await {
             ServerMessage::SetEnded { lifes } => {
-                println!("Asserted game msg {:?}", ServerMessage::SetEnded { lifes });
-                false
+                println!("Asserted game msg SetEnded {lifes:?}");
+                Some(lifes)
             }
             ServerMessage::GameEnded { winner, lifes } => {
                 let expected_life_values = if winner.is_some() { 1 } else { 0 };
 
-                assert!(lifes.len() == expected_life_values);
+                assert!(lifes.values().filter(|l| **l > 0).count() == expected_life_values);
 
                 let msg = ServerMessage::GameEnded { lifes, winner };
 
                 println!("Asserted game msg {:?}", msg);
 
-                true
+                None
             }
             msg => panic!("Expected Set or Game end | {msg:?}"),
         }
@@ -171,11 +202,12 @@ mod tests {
 
         let mut connections = HashMap::new();
 
-        for p in tokens {
+        // half the table negotiates batches, the other half is a legacy client
+        for (i, p) in tokens.into_iter().enumerate() {
             let claims = get_claims_from_token(&p).await.unwrap();
 
             let data = PlayerData {
-                connection: connect_ws(p.clone()).await,
+                connection: connect_ws(p.clone(), i % 2 == 0).await,
                 deck: Vec::new(),
             };
 
@@ -189,7 +221,7 @@ mod tests {
         let msg = ClientGameMessage::PlayerStatusChange { ready: true };
 
         for p in players.values_mut() {
-            send_msg(&mut p.connection, msg).await;
+            send_msg(&mut p.connection, msg.clone()).await;
         }
 
         for _ in 0..players.len() {
@@ -285,30 +317,75 @@ mod tests {
     }
 
     async fn send_msg(stream: &mut WebSocket, msg: ClientGameMessage) {
-        let msg = ClientMessage::Game(msg);
+        send_client_msg(&mut stream.stream, ClientMessage::Game(msg)).await;
+    }
 
+    async fn send_client_msg(stream: &mut Stream, msg: ClientMessage) {
         let msg = serde_json::to_string(&msg).unwrap();
 
         stream.send(Message::Text(msg)).await.unwrap();
     }
 
-    type WebSocket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+    type Stream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    struct WebSocket {
+        stream: Stream,
+        batch: bool,
+        last_seq: u64,
+        pending: VecDeque<ServerMessage>,
+    }
 
-    async fn connect_ws(token: String) -> WebSocket {
+    async fn connect_ws(token: String, batch: bool) -> WebSocket {
         let (mut stream, _) = connect_async("ws://localhost:3000/game").await.unwrap();
 
-        let msg = ClientMessage::Auth { token };
+        if batch {
+            let hello = ClientMessage::Hello {
+                protocol_version: PROTOCOL_VERSION,
+                capabilities: vec![BATCH.to_string()],
+            };
 
-        let json = serde_json::to_string(&msg).unwrap();
+            send_client_msg(&mut stream, hello).await;
 
-        stream.send(Message::Text(json)).await.unwrap();
+            match read_msg(&mut stream).await {
+                ServerMessage::Welcome(w) => assert!(w.capabilities.contains(&BATCH.to_string())),
+                msg => panic!("Expected welcome | {msg:?}"),
+            }
+        }
+
+        send_client_msg(&mut stream, ClientMessage::Auth { token }).await;
 
         assert!(!stream.is_terminated());
 
-        stream
+        WebSocket {
+            stream,
+            batch,
+            last_seq: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    async fn recv_msg(socket: &mut WebSocket) -> ServerMessage {
+        loop {
+            if let Some(msg) = socket.pending.pop_front() {
+                return msg;
+            }
+
+            match (read_msg(&mut socket.stream).await, socket.batch) {
+                (ServerMessage::Batch(batch), true) => {
+                    assert!(batch.seq >= socket.last_seq, "Batch out of order");
+                    assert!(!batch.events.is_empty());
+
+                    socket.last_seq = batch.seq;
+                    socket.pending.extend(batch.events);
+                }
+                (msg @ ServerMessage::Batch(_), false) => panic!("Legacy client got {msg:?}"),
+                (msg, true) => panic!("Expected a batch | {msg:?}"),
+                (msg, false) => return msg,
+            }
+        }
     }
 
-    async fn recv_msg(stream: &mut WebSocket) -> ServerMessage {
+    async fn read_msg(stream: &mut Stream) -> ServerMessage {
         let msg = stream.next().await.unwrap().unwrap();
 
         let msg: ServerMessage = match msg {